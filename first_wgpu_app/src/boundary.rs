@@ -0,0 +1,66 @@
+//! What lies beyond the edges of the grid.
+//!
+//! The compute shaders look up every neighbor through `cellState` in
+//! `c_shader.wgsl`, which maps coordinates outside the grid according to the
+//! boundary mode stored in the grid uniform.
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Opposite edges are joined, so patterns leaving one side re-enter on the other.
+    #[default]
+    Torus,
+    /// Everything outside the grid is permanently dead.
+    Dead,
+    /// The grid is mirrored along its edges, so edge cells see themselves as neighbors.
+    Mirror,
+    /// Left and right edges are joined like a torus, top and bottom are joined with a twist.
+    Klein,
+    /// Both pairs of edges are joined with a twist (the real projective plane).
+    Cross,
+}
+
+impl Boundary {
+    pub const ALL: [Boundary; 5] = [
+        Boundary::Torus,
+        Boundary::Dead,
+        Boundary::Mirror,
+        Boundary::Klein,
+        Boundary::Cross,
+    ];
+
+    /// The value identifying this mode in the shaders' `Grid` uniform.
+    pub fn shader_value(&self) -> u32 {
+        *self as u32
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Boundary::Torus => "torus",
+            Boundary::Dead => "dead",
+            Boundary::Mirror => "mirror",
+            Boundary::Klein => "klein",
+            Boundary::Cross => "cross",
+        }
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Boundary::ALL
+            .into_iter()
+            .find(|b| b.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Boundary::ALL.iter().map(Boundary::name).collect();
+                format!("unknown boundary {s:?}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -1,42 +1,447 @@
-@group(0) @binding(0) var<uniform> grid: vec2f;
+// `boundary` says how neighbors beyond the edges are found, see `boundary.rs`.
+// `packed` is 1 if every u32 of the cell state holds 32 cells instead of one.
+struct Grid {
+    size: vec2f,
+    boundary: u32,
+    packed: u32,
+};
+@group(0) @binding(0) var<uniform> grid: Grid;
+
+const TORUS = 0u;
+const DEAD = 1u;
+const MIRROR = 2u;
+const KLEIN = 3u;
+const CROSS = 4u;
 
 @group(0) @binding(1) var<storage> cellStateIn: array<u32>;
 @group(0) @binding(2) var<storage, read_write> cellStateOut: array<u32>;
 
+// Bit n of `birth` / `survival` is set if n active neighbors cause a birth / survival.
+// `states` is 2 for Life-like rules, more for Generations rules with decay states.
+// `table` is 1 if the automaton is driven by the rule table instead.
+struct Rule {
+    birth: u32,
+    survival: u32,
+    states: u32,
+    table: u32,
+};
+@group(0) @binding(3) var<uniform> rule: Rule;
+
+// A rule table expanded into bitsets over its rules, see `table.rs`.
+// `data` holds `inputs * rule.states * words` bitset words followed by the
+// output state of each rule.
+struct Table {
+    words: u32,
+    inputs: u32,
+    data: array<u32>,
+};
+@group(0) @binding(4) var<storage> table: Table;
+
+// Neighbor offsets in the order rule tables list their inputs.
+var<private> moore: array<vec2i, 9> = array(
+    vec2i(0, 0), vec2i(0, 1), vec2i(1, 1), vec2i(1, 0), vec2i(1, -1),
+    vec2i(0, -1), vec2i(-1, -1), vec2i(-1, 0), vec2i(-1, 1),
+);
+var<private> vonNeumann: array<vec2i, 5> = array(
+    vec2i(0, 0), vec2i(0, 1), vec2i(1, 0), vec2i(0, -1), vec2i(-1, 0),
+);
+
 fn cellIndex(cell: vec2u) -> u32 {
-    return (cell.y % u32(grid.y)) * u32(grid.x) +
-        (cell.x % u32(grid.x));
+    return cell.y * u32(grid.size.x) + cell.x;
+}
+
+// Where the cell at `pos`, which may lie beyond the edges of the grid, is
+// found inside it, or -1 for cells that are always dead.
+fn boundaryCell(pos: vec2i) -> vec2i {
+    let size = vec2i(grid.size);
+    let outsideX = pos.x < 0 || pos.x >= size.x;
+    let outsideY = pos.y < 0 || pos.y >= size.y;
+
+    var p = pos;
+    switch grid.boundary {
+        case DEAD: {
+            if outsideX || outsideY {
+                return vec2i(-1);
+            }
+        }
+        case MIRROR: {
+            p = clamp(p, vec2i(0), size - 1);
+        }
+        case KLEIN: { // Crossing the top or bottom edge flips left and right.
+            if outsideY {
+                p.x = size.x - 1 - p.x;
+            }
+        }
+        case CROSS: { // Crossing any edge flips the other axis.
+            if outsideY {
+                p.x = size.x - 1 - p.x;
+            }
+            if outsideX {
+                p.y = size.y - 1 - p.y;
+            }
+        }
+        default: {}
+    }
+
+    // Whatever is still outside wraps around to the opposite edge. Coordinates are
+    // at most one cell outside, so this only ever takes `%` of non-negative values,
+    // which some backends get wrong for negative ones.
+    return (p + size) % size;
 }
 
-fn cellActive(x: u32, y: u32) -> u32 {
-  return cellStateIn[cellIndex(vec2(x, y))];
+// The state of the cell at `pos`, which may lie beyond the edges of the grid.
+fn cellState(pos: vec2i) -> u32 {
+    let p = boundaryCell(pos);
+    if p.x < 0 {
+        return 0u;
+    }
+    return cellStateIn[cellIndex(vec2u(p))];
+}
+
+// Only fully alive cells (state 1) count as neighbors, decaying cells do not.
+fn cellActive(pos: vec2i) -> u32 {
+  return u32(cellState(pos) == 1u);
+}
+
+// The next state of a cell of a Life-like or Generations rule.
+fn nextState(state: u32, activeNeighbors: u32) -> u32 {
+    if state == 0u {
+        // Dead cells are born according to the birth mask.
+        return (rule.birth >> activeNeighbors) & 1u;
+    } else if state == 1u && ((rule.survival >> activeNeighbors) & 1u) == 1u {
+        return 1u;
+    }
+    // Live cells that do not survive, and decaying cells, move to the next
+    // state, wrapping around to dead. With 2 states this is just death.
+    return (state + 1u) % rule.states;
 }
 
 @compute @workgroup_size(8, 8)
 fn compute_main(@builtin(global_invocation_id) cell: vec3u) {
+    if any(cell.xy >= vec2u(grid.size)) {
+        return;
+    }
+
     // Determine how many active neighbors this cell has.
+    let pos = vec2i(cell.xy);
     let activeNeighbors = 
-            cellActive(cell.x+1, cell.y+1) +
-            cellActive(cell.x+1, cell.y) +
-            cellActive(cell.x+1, cell.y-1) +
-            cellActive(cell.x, cell.y-1) +
-            cellActive(cell.x-1, cell.y-1) +
-            cellActive(cell.x-1, cell.y) +
-            cellActive(cell.x-1, cell.y+1) +
-            cellActive(cell.x, cell.y+1);
+            cellActive(pos + vec2i(1, 1)) +
+            cellActive(pos + vec2i(1, 0)) +
+            cellActive(pos + vec2i(1, -1)) +
+            cellActive(pos + vec2i(0, -1)) +
+            cellActive(pos + vec2i(-1, -1)) +
+            cellActive(pos + vec2i(-1, 0)) +
+            cellActive(pos + vec2i(-1, 1)) +
+            cellActive(pos + vec2i(0, 1));
     
     let i = cellIndex(cell.xy);
+    cellStateOut[i] = nextState(cellStateIn[i], activeNeighbors);
+}
+
+// The cells of one workgroup plus a one cell border, so that each cell is read
+// from storage once per workgroup instead of nine times.
+const TILE_SIZE = 8u;
+const HALO_SIZE = TILE_SIZE + 2u;
+var<workgroup> tile: array<array<u32, HALO_SIZE>, HALO_SIZE>;
 
-    // Conway's game of life rules:
-    switch activeNeighbors {
-        case 2u: { // Active cells with 2 neighbors stay active.
-            cellStateOut[i] = cellStateIn[i];
+// Same rules as `compute_main`, reading the neighborhood from workgroup memory.
+@compute @workgroup_size(TILE_SIZE, TILE_SIZE)
+fn compute_tiled(
+    @builtin(workgroup_id) group: vec3u,
+    @builtin(local_invocation_id) local: vec3u,
+    @builtin(local_invocation_index) index: u32,
+) {
+    // The 64 invocations share loading the 100 cells of the tile.
+    let origin = vec2i(group.xy * TILE_SIZE) - 1;
+    for (var k = index; k < HALO_SIZE * HALO_SIZE; k += TILE_SIZE * TILE_SIZE) {
+        let t = vec2u(k % HALO_SIZE, k / HALO_SIZE);
+        tile[t.y][t.x] = cellState(origin + vec2i(t));
+    }
+    workgroupBarrier();
+
+    let cell = group.xy * TILE_SIZE + local.xy;
+    if any(cell >= vec2u(grid.size)) {
+        return;
+    }
+
+    var activeNeighbors = 0u;
+    for (var dy = 0u; dy < 3u; dy++) {
+        for (var dx = 0u; dx < 3u; dx++) {
+            if dx != 1u || dy != 1u {
+                activeNeighbors += u32(tile[local.y + dy][local.x + dx] == 1u);
+            }
         }
-        case 3u: { // Cells with 3 neighbors become or stay active.
-            cellStateOut[i] = 1;
+    }
+
+    let state = tile[local.y + 1u][local.x + 1u];
+    cellStateOut[cellIndex(cell)] = nextState(state, activeNeighbors);
+}
+
+@compute @workgroup_size(8, 8)
+fn compute_table(@builtin(global_invocation_id) cell: vec3u) {
+    if any(cell.xy >= vec2u(grid.size)) {
+        return;
+    }
+
+    // Gather the states of the neighborhood, center cell first.
+    var states: array<u32, 9>;
+    for (var p = 0u; p < table.inputs; p++) {
+        var offset = moore[p];
+        if table.inputs == 5u {
+            offset = vonNeumann[p];
+        }
+        states[p] = cellState(vec2i(cell.xy) + offset);
+    }
+
+    let i = cellIndex(cell.xy);
+    let lookupSize = table.inputs * rule.states * table.words;
+
+    // Cells matching no rule keep their state.
+    var next = cellStateIn[i];
+    for (var w = 0u; w < table.words; w++) {
+        // Rules accepting every state of the neighborhood, 32 rules at a time.
+        var matching = 0xffffffffu;
+        for (var p = 0u; p < table.inputs && matching != 0u; p++) {
+            matching &= table.data[(p * rule.states + states[p]) * table.words + w];
         }
-        default: { // Cells with < 2 or > 3 neighbors become inactive.
-            cellStateOut[i] = 0;
+        if matching != 0u {
+            // The first matching rule wins, like in Golly.
+            next = table.data[lookupSize + w * 32u + firstTrailingBit(matching)];
+            break;
         }
     }
-}
\ No newline at end of file
+    cellStateOut[i] = next;
+}
+
+// In the packed layout, bit b of word (y * wordsPerRow + xw) is the cell (xw * 32 + b, y),
+// and the grid width is a multiple of 32.
+// Returns the word at (xw, y), which may lie beyond the edges of the grid. For words
+// beyond the left or right edge only the bit next to the edge is meaningful.
+fn packedWord(xw: i32, y: i32) -> u32 {
+    let size = vec2i(i32(grid.size.x) / 32, i32(grid.size.y));
+    let outsideX = xw < 0 || xw >= size.x;
+    let outsideY = y < 0 || y >= size.y;
+
+    // Flipping a row left to right means reversing the order of its words and their bits.
+    var p = vec2i(xw, y);
+    var reversed = false;
+    switch grid.boundary {
+        case DEAD: {
+            if outsideX || outsideY {
+                return 0u;
+            }
+        }
+        case MIRROR: {
+            reversed = outsideX;
+            p = clamp(p, vec2i(0), size - 1);
+        }
+        case KLEIN: {
+            if outsideY {
+                p.x = size.x - 1 - p.x;
+                reversed = true;
+            }
+        }
+        case CROSS: {
+            if outsideY {
+                p.x = size.x - 1 - p.x;
+                reversed = true;
+            }
+            if outsideX {
+                p.y = size.y - 1 - p.y;
+            }
+        }
+        default: {}
+    }
+
+    p = (p + size) % size;
+    let word = cellStateIn[u32(p.y * size.x + p.x)];
+    return select(word, reverseBits(word), reversed);
+}
+
+// Adds the one-bit values in `x` to a bit-sliced counter: component k holds
+// bit k of the counts of all 32 cells.
+fn addBits(count: vec4u, x: u32) -> vec4u {
+    let carry0 = count.x & x;
+    let carry1 = count.y & carry0;
+    let carry2 = count.z & carry1;
+    return vec4u(count.x ^ x, count.y ^ carry0, count.z ^ carry1, count.w | carry2);
+}
+
+// Steps 32 cells of a Life-like rule at once on the packed layout.
+@compute @workgroup_size(8, 8)
+fn compute_packed(@builtin(global_invocation_id) id: vec3u) {
+    let wordsPerRow = u32(grid.size.x) / 32u;
+    if id.x >= wordsPerRow || id.y >= u32(grid.size.y) {
+        return;
+    }
+
+    let xw = i32(id.x);
+    let y = i32(id.y);
+    var count = vec4u(0u);
+    for (var dy = -1; dy <= 1; dy++) {
+        let left = packedWord(xw - 1, y + dy);
+        let center = packedWord(xw, y + dy);
+        let right = packedWord(xw + 1, y + dy);
+        // The neighbors to the west and to the east of every cell in the word.
+        count = addBits(count, (center << 1u) | (left >> 31u));
+        count = addBits(count, (center >> 1u) | (right << 31u));
+        if dy != 0 {
+            count = addBits(count, center);
+        }
+    }
+
+    let i = id.y * wordsPerRow + id.x;
+    let alive = cellStateIn[i];
+    var next = 0u;
+    for (var n = 0u; n <= 8u; n++) {
+        // The cells whose neighbor count is exactly n.
+        var equal = 0xffffffffu;
+        for (var k = 0u; k < 4u; k++) {
+            equal &= select(~count[k], count[k], ((n >> k) & 1u) == 1u);
+        }
+        if ((rule.birth >> n) & 1u) == 1u {
+            next |= equal & ~alive;
+        }
+        if ((rule.survival >> n) & 1u) == 1u {
+            next |= equal & alive;
+        }
+    }
+    cellStateOut[i] = next;
+}
+
+// What `take_census` finds out about the current generation. Both are sums
+// over the cells, so that workgroups can add their share in any order.
+struct Census {
+    // The number of non-dead cells.
+    population: atomic<u32>,
+    // Two independent 32-bit hashes of the grid, the low and high half of a
+    // 64-bit hash that tells generations apart.
+    hash: array<atomic<u32>, 2>,
+}
+@group(0) @binding(5) var<storage, read_write> census: Census;
+
+const REDUCTION_SIZE = 256u;
+// The population and both hashes counted by each invocation of a workgroup.
+var<workgroup> partialCensus: array<vec3u, REDUCTION_SIZE>;
+
+// Chris Wellons' lowbias32 integer hash.
+fn mix(x: u32) -> u32 {
+    var h = x;
+    h ^= h >> 16u;
+    h *= 0x7feb352du;
+    h ^= h >> 15u;
+    h *= 0x846ca68bu;
+    h ^= h >> 16u;
+    return h;
+}
+
+// Adds the population and hash of the current generation to `census`, which
+// must be cleared first. Each invocation counts a strided share of the cell
+// buffer, the workgroup sums those counts in a tree, and one atomic addition
+// per workgroup and field adds the result. Dead words add nothing, and every
+// other word adds hashes of its contents mixed with its index.
+@compute @workgroup_size(REDUCTION_SIZE)
+fn take_census(
+    @builtin(global_invocation_id) id: vec3u,
+    @builtin(local_invocation_index) index: u32,
+    @builtin(num_workgroups) groups: vec3u,
+) {
+    var sums = vec3u(0u);
+    let words = arrayLength(&cellStateIn);
+    for (var i = id.x; i < words; i += groups.x * REDUCTION_SIZE) {
+        let word = cellStateIn[i];
+        if word == 0u {
+            continue;
+        }
+        let population = select(1u, countOneBits(word), grid.packed == 1u);
+        sums += vec3u(population, mix(word ^ mix(i)), mix(word ^ mix(i ^ 0x9e3779b9u)));
+    }
+    partialCensus[index] = sums;
+    workgroupBarrier();
+
+    for (var stride = REDUCTION_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if index < stride {
+            partialCensus[index] += partialCensus[index + stride];
+        }
+        workgroupBarrier();
+    }
+    if index == 0u {
+        let total = partialCensus[0];
+        atomicAdd(&census.population, total.x);
+        atomicAdd(&census.hash[0], total.y);
+        atomicAdd(&census.hash[1], total.z);
+    }
+}
+
+// What the window shows about each cell besides its state, see `coloring.rs`:
+// how many generations it has been non-dead in bits 0 to 15, its active
+// neighbors in bits 16 to 19, and how recently it changed state in bits 20 to
+// 31, from MAX_HEAT for the last generation down to 0.
+@group(1) @binding(0) var<storage, read_write> activity: array<u32>;
+
+const MAX_AGE = 0xffffu;
+const MAX_HEAT = 0xfffu;
+const HEAT_DECAY = 0.9;
+
+// The state of cell `i` in the input or output buffer, in either layout.
+fn stateIn(i: u32) -> u32 {
+    if grid.packed == 1u {
+        return (cellStateIn[i / 32u] >> (i % 32u)) & 1u;
+    }
+    return cellStateIn[i];
+}
+fn stateOut(i: u32) -> u32 {
+    if grid.packed == 1u {
+        return (cellStateOut[i / 32u] >> (i % 32u)) & 1u;
+    }
+    return cellStateOut[i];
+}
+
+// The number of neighbors of `cell` in state 1, in either layout.
+fn activeNeighbors(cell: vec2u) -> u32 {
+    var count = 0u;
+    for (var n = 1u; n < 9u; n++) {
+        let p = boundaryCell(vec2i(cell) + moore[n]);
+        if p.x >= 0 && stateIn(cellIndex(vec2u(p))) == 1u {
+            count++;
+        }
+    }
+    return count;
+}
+
+// Updates the activity of every cell after a step. The bind group is that of
+// the new generation, so the output buffer still holds the previous one.
+@compute @workgroup_size(8, 8)
+fn track_cells(@builtin(global_invocation_id) cell: vec3u) {
+    if any(cell.xy >= vec2u(grid.size)) {
+        return;
+    }
+    let i = cellIndex(cell.xy);
+    let old = activity[i];
+    let state = stateIn(i);
+
+    var age = 0u;
+    if state != 0u {
+        age = min((old & MAX_AGE) + 1u, MAX_AGE);
+    }
+    var heat = u32(f32(old >> 20u) * HEAT_DECAY);
+    if state != stateOut(i) {
+        heat = MAX_HEAT;
+    }
+    activity[i] = age | (activeNeighbors(cell.xy) << 16u) | (heat << 20u);
+}
+
+// Brings the neighbor counts up to date after the current generation was
+// edited, without counting a generation. Erased cells lose their age.
+@compute @workgroup_size(8, 8)
+fn recount_neighbors(@builtin(global_invocation_id) cell: vec3u) {
+    if any(cell.xy >= vec2u(grid.size)) {
+        return;
+    }
+    let i = cellIndex(cell.xy);
+    var kept = activity[i] & (MAX_HEAT << 20u);
+    if stateIn(i) != 0u {
+        kept |= activity[i] & MAX_AGE;
+    }
+    activity[i] = kept | (activeNeighbors(cell.xy) << 16u);
+}
@@ -0,0 +1,122 @@
+//! The view of the grid shown in the window.
+//!
+//! Positions are measured in cells, with row 0 at the bottom as in the cell
+//! buffers, and a cell is as many pixels wide as it is high whatever the shape
+//! of the window.
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+/// Zooming never goes further out than this fraction of the whole grid
+/// fitting the window.
+const MIN_ZOOM: f64 = 0.25;
+/// The largest a cell gets, in pixels.
+const MAX_PIXELS_PER_CELL: f64 = 256.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// The point of the grid in the middle of the window, in cells.
+    pub center: (f64, f64),
+    /// The width and height of a cell on screen.
+    pub pixels_per_cell: f64,
+}
+
+impl Camera {
+    /// A camera showing the whole of a `width` by `height` grid in the middle
+    /// of the window.
+    pub fn fit((width, height): (u32, u32), window: PhysicalSize<u32>) -> Self {
+        let (width, height) = (f64::from(width), f64::from(height));
+        Camera {
+            center: (width / 2.0, height / 2.0),
+            pixels_per_cell: (f64::from(window.width.max(1)) / width).min(f64::from(window.height.max(1)) / height),
+        }
+    }
+
+    /// The point of the grid under `position` in the window, in cells.
+    pub fn to_cells(&self, position: PhysicalPosition<f64>, window: PhysicalSize<u32>) -> (f64, f64) {
+        let dx = position.x - f64::from(window.width) / 2.0;
+        // Window rows count from the top, grid rows from the bottom.
+        let dy = f64::from(window.height) / 2.0 - position.y;
+        (
+            self.center.0 + dx / self.pixels_per_cell,
+            self.center.1 + dy / self.pixels_per_cell,
+        )
+    }
+
+    /// Moves the view along with the cursor moving by `delta` pixels.
+    pub fn pan(&mut self, delta: (f64, f64)) {
+        self.center.0 -= delta.0 / self.pixels_per_cell;
+        self.center.1 += delta.1 / self.pixels_per_cell;
+    }
+
+    /// Scales cells by `factor`, keeping the point under `anchor` where it is.
+    /// The zoom is bounded between the grid filling a quarter of the window
+    /// and a cell filling `MAX_PIXELS_PER_CELL` pixels.
+    pub fn zoom(&mut self, factor: f64, anchor: PhysicalPosition<f64>, window: PhysicalSize<u32>, grid_size: (u32, u32)) {
+        let min = MIN_ZOOM * Camera::fit(grid_size, window).pixels_per_cell;
+        let pixels_per_cell = (self.pixels_per_cell * factor).clamp(min.min(MAX_PIXELS_PER_CELL), MAX_PIXELS_PER_CELL);
+        let before = self.to_cells(anchor, window);
+        self.pixels_per_cell = pixels_per_cell;
+        let after = self.to_cells(anchor, window);
+        self.center.0 += before.0 - after.0;
+        self.center.1 += before.1 - after.1;
+    }
+
+    /// The contents of the camera uniform, laid out like `Camera` in
+    /// `shader.wgsl`: the center in cells, then the size of a cell in clip
+    /// space, which is 2 across the window, for each axis.
+    pub fn uniform_data(&self, window: PhysicalSize<u32>) -> [f32; 4] {
+        let scale = |pixels: u32| (2.0 * self.pixels_per_cell / f64::from(pixels.max(1))) as f32;
+        [
+            self.center.0 as f32,
+            self.center.1 as f32,
+            scale(window.width),
+            scale(window.height),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: PhysicalSize<u32> = PhysicalSize::new(800, 600);
+
+    fn assert_same_point(a: (f64, f64), b: (f64, f64)) {
+        assert!((a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn fits_the_grid_in_the_window() {
+        let camera = Camera::fit((100, 100), WINDOW);
+        assert_eq!(camera.pixels_per_cell, 6.0);
+        assert_eq!(camera.to_cells(PhysicalPosition::new(400.0, 300.0), WINDOW), (50.0, 50.0));
+        assert_eq!(camera.to_cells(PhysicalPosition::new(100.0, 600.0), WINDOW), (0.0, 0.0));
+        // Cells are square, the extra width of the window is left over.
+        assert_eq!(camera.uniform_data(WINDOW), [50.0, 50.0, 0.015, 0.02]);
+
+        // A wide grid is bounded by the width of the window instead.
+        let camera = Camera::fit((400, 100), WINDOW);
+        assert_eq!(camera.pixels_per_cell, 2.0);
+        assert_eq!(camera.center, (200.0, 50.0));
+    }
+
+    #[test]
+    fn zooming_keeps_the_cell_under_the_cursor() {
+        let mut camera = Camera::fit((100, 100), WINDOW);
+        let cursor = PhysicalPosition::new(123.0, 456.0);
+        let before = camera.to_cells(cursor, WINDOW);
+        camera.zoom(3.0, cursor, WINDOW, (100, 100));
+        assert_eq!(camera.pixels_per_cell, 18.0);
+        assert_same_point(camera.to_cells(cursor, WINDOW), before);
+
+        camera.zoom(1e-6, cursor, WINDOW, (100, 100));
+        assert_eq!(camera.pixels_per_cell, 1.5);
+    }
+
+    #[test]
+    fn panning_follows_the_cursor() {
+        let mut camera = Camera::fit((100, 100), WINDOW);
+        let grabbed = camera.to_cells(PhysicalPosition::new(200.0, 200.0), WINDOW);
+        camera.pan((30.0, -60.0));
+        assert_same_point(camera.to_cells(PhysicalPosition::new(230.0, 140.0), WINDOW), grabbed);
+    }
+}
@@ -0,0 +1,147 @@
+//! Conversions between the cell buffers and other representations of a grid.
+//!
+//! The cell buffers store row 0 at the bottom of the screen, since the
+//! shaders place cells in clip space where y points up. Pattern files list
+//! row 0 at the top, so stamping and exporting patterns flips the rows.
+use crate::pattern::Pattern;
+
+/// Packs one state per cell into 32 cells per word, as used by the packed layout.
+/// Any non-dead state counts as alive.
+pub fn pack(cells: &[u32]) -> Vec<u32> {
+    cells
+        .chunks(32)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |word, (bit, &state)| word | (u32::from(state != 0) << bit))
+        })
+        .collect()
+}
+
+/// The inverse of [`pack`], for a grid of `cell_count` cells.
+pub fn unpack(words: &[u32], cell_count: usize) -> Vec<u32> {
+    (0..cell_count)
+        .map(|i| (words[i / 32] >> (i % 32)) & 1)
+        .collect()
+}
+
+/// Sets the cell at `(x, y)`, row 0 at the bottom, in a grid `width` cells
+/// wide. With `packed`, the grid holds 32 cells per word and any non-dead
+/// state sets the cell alive.
+pub fn set(cells: &mut [u32], width: u32, packed: bool, (x, y): (u32, u32), state: u32) {
+    let i = y as usize * width as usize + x as usize;
+    if packed {
+        let bit = 1 << (i % 32);
+        if state != 0 {
+            cells[i / 32] |= bit;
+        } else {
+            cells[i / 32] &= !bit;
+        }
+    } else {
+        cells[i] = state;
+    }
+}
+
+/// Writes `pattern` into a grid of `width` by `height` cells with its top left
+/// corner at column `at.0` and row `at.1`, counting rows from the top.
+/// Returns the number of cells that fell outside the grid and were dropped.
+pub fn stamp(
+    cells: &mut [u32],
+    width: u32,
+    height: u32,
+    packed: bool,
+    pattern: &Pattern,
+    at: (u32, u32),
+) -> usize {
+    let mut dropped = 0;
+    for &(x, y, state) in &pattern.cells {
+        let (column, row) = (at.0 as u64 + x as u64, at.1 as u64 + y as u64);
+        if column >= width as u64 || row >= height as u64 {
+            dropped += 1;
+            continue;
+        }
+        let y_up = height - 1 - row as u32;
+        set(cells, width, packed, (column as u32, y_up), state);
+    }
+    dropped
+}
+
+/// The top left corner that centers a pattern in a grid, or the grid's corner
+/// if the pattern is larger.
+pub fn centered(width: u32, height: u32, pattern: &Pattern) -> (u32, u32) {
+    (
+        width.saturating_sub(pattern.width) / 2,
+        height.saturating_sub(pattern.height) / 2,
+    )
+}
+
+/// The cells of a grid as a pattern, cropped to its live cells.
+pub fn to_pattern(cells: &[u32], width: u32, height: u32) -> Pattern {
+    let top_down: Vec<u32> = cells
+        .chunks(width as usize)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    Pattern::from_grid(&top_down, width, height)
+}
+
+/// Copies the cells of a `from` grid, given as `(width, height)`, into the
+/// middle of a `to` grid, cropping them if it is smaller. The rest of the new
+/// grid is dead.
+pub fn recenter(cells: &[u32], from: (u32, u32), to: (u32, u32)) -> Vec<u32> {
+    let mut recentered = vec![0; to.0 as usize * to.1 as usize];
+    // How far the old grid moves, the same in every direction it grows or shrinks.
+    let shift = |from: u32, to: u32| (i64::from(to) - i64::from(from)) / 2;
+    let (dx, dy) = (shift(from.0, to.0), shift(from.1, to.1));
+    for (i, &state) in cells.iter().enumerate().filter(|&(_, &state)| state != 0) {
+        let x = (i % from.0 as usize) as i64 + dx;
+        let y = (i / from.0 as usize) as i64 + dy;
+        if (0..i64::from(to.0)).contains(&x) && (0..i64::from(to.1)).contains(&y) {
+            recentered[(y * i64::from(to.0) + x) as usize] = state;
+        }
+    }
+    recentered
+}
+
+/// A copy of one generation read back from the GPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub generation: u64,
+    /// Whether `words` hold 32 cells each, as in the packed layout, or one state per cell.
+    pub packed: bool,
+    /// The contents of the cell buffer, row 0 at the bottom.
+    pub words: Vec<u32>,
+}
+
+impl Snapshot {
+    fn cell_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// One state per cell, row 0 at the bottom.
+    pub fn states(&self) -> Vec<u32> {
+        if self.packed {
+            unpack(&self.words, self.cell_count())
+        } else {
+            self.words.clone()
+        }
+    }
+
+    /// The number of non-dead cells.
+    pub fn population(&self) -> u64 {
+        if self.packed {
+            self.words.iter().map(|word| u64::from(word.count_ones())).sum()
+        } else {
+            self.words.iter().filter(|&&state| state != 0).count() as u64
+        }
+    }
+
+    /// The non-dead cells as a pattern, cropped to their bounding box.
+    pub fn to_pattern(&self) -> Pattern {
+        to_pattern(&self.states(), self.width, self.height)
+    }
+}
@@ -0,0 +1,578 @@
+//! Command line options.
+use std::{fmt, fs, ops::RangeInclusive, path::Path, str::FromStr};
+
+use crate::{
+    boundary::Boundary,
+    pattern::{Format, Pattern},
+    rule::Automaton,
+    seed::Generator,
+    coloring::ColorMode,
+    pacing::{FRAMES_PER_SECOND, GENERATIONS_PER_SECOND},
+    palette::{self, Palette},
+    stability::OnStable,
+};
+
+pub const USAGE: &str = "\
+Usage: first_wgpu_app [OPTIONS] [RULE]
+
+RULE is a rulestring such as B36/S23 or B2/S/C3, a Golly rule table file
+such as rules/WireWorld.rule, or wireworld. Defaults to the rule of the
+pattern file if there is one, or B3/S23.
+
+Options:
+  --config <FILE>      Read options from a file of `option = value` lines, such
+                       as `seed = 42` or `packed = true`, one for every long
+                       option and `rule`; options on the command line win, and
+                       the files it names are relative to it
+  --init <GENERATOR>   How to fill the first generation: random, all over the
+                       grid; blob, a square soup of random cells in the middle;
+                       symmetric, a soup mirrored both ways; or pattern
+                       [default: random, or pattern with --pattern]
+  --seed <N>           Seed for the random generators; the seed of every run is
+                       printed, so that it can be repeated [default: random]
+  --density <P>        Fraction of live cells in random soups [default: 0.4]
+  --soup-size <N>      Width and height of the blob and symmetric soups
+                       [default: 16]
+  --pattern <FILE>     Start from a pattern file: .rle, .cells, .lif or .life
+                       (Life 1.05 or 1.06), or .mc
+  --at <X>,<Y>         Place the top left corner of the pattern at column X and
+                       row Y, counting rows from the top [default: centered]
+  --boundary <MODE>    torus, dead, mirror, klein or cross [default: torus]
+  --grid-size <SIZE>   Size of the grid in cells, N for a square grid or WxH
+                       for one W cells wide and H high [default: 128]
+  --packed             Store 32 cells per word, for very large Life-like grids;
+                       the grid width must be a multiple of 32
+  --kernel <KERNEL>    Compute kernel for totalistic rules: naive, which reads
+                       every neighbor from storage, or tiled, which shares a
+                       tile of cells in workgroup memory [default: naive]
+  --renderer <MODE>    How to draw the grid: quads, one for every cell, or
+                       fullscreen, which looks up the cell under every pixel
+                       and suits large grids better [default: quads, or
+                       fullscreen for grids of over 2048x2048 cells]
+  --smooth             With the fullscreen renderer, blend the cells sharing a
+                       pixel when zoomed out, instead of showing one of them
+  --colors <MODE>      Color cells by position; age, the generations they have
+                       been alive; heat, how recently they changed; or
+                       neighbors, how many live ones they have
+                       [default: position]
+  --palette <FILE>     Draw with the colors of a palette file, such as
+                       palettes/embers.toml; give it several times to switch
+                       between them with P [default: built in]
+  --sps <N>            Generations simulated per second, independent of the
+                       frame rate; several generations are run per frame if
+                       needed [default: 5]
+  --fps <N>            Frames drawn per second [default: 60]
+  --history <N>        Generations kept on the GPU for rewinding in the window,
+                       including the current one; each takes as much memory
+                       as the grid, so large grids keep fewer, up to 256 MiB
+                       in all, and 0 turns rewinding off [default: 64]
+  --benchmark <N>      Run N generations with every kernel without opening a
+                       window, and report generations per second
+  --headless <N>       Run N generations without opening a window, and save
+                       the last one to the --output file
+  --output <FILE>      Where --headless saves the last generation, as .rle,
+                       .cells, .lif, .life or .mc [default: generation-<N>.rle]
+  --on-stable <ACTION> What to do once the world dies out or settles into a
+                       still life or oscillator: log it, pause (a headless run
+                       stops early), or reseed with the next seed; the window
+                       only logs it while it draws every generation, unless
+                       --population is given [default: log]
+  --population <FILE>  Write the population of every generation counted to a
+                       CSV file, when the window closes or --headless ends
+  --fallback-adapter   Only use a software adapter, such as lavapipe or llvmpipe
+  -h, --help           Print this help
+
+Controls:
+  Space                Pause or resume the simulation
+  N                    Pause and advance a single generation
+  Left / Right         Pause and go back or forward a generation through the
+                       history; the simulation resumes from the one on screen
+  S                    Save the current generation to generation-<N>.rle
+  + / -                Double or halve the generations per second
+  Left mouse button    Paint live cells
+  Right mouse button   Erase cells
+  Mouse wheel          Zoom in or out around the cursor
+  Middle mouse button  Drag the view around
+  Home                 Show the whole grid
+  C                    Switch to the next color mode
+  P                    Switch to the next palette, the built in one last
+  [ / ]                Halve or double the width and height of the grid,
+                       keeping the cells in the middle";
+
+/// Grid sizes are limited so that the number of cells fits in a `u32`.
+pub const MAX_GRID_SIZE: u32 = 65535;
+
+/// Grids with more cells than this are drawn by the fullscreen renderer unless
+/// `--renderer` says otherwise: a quad per cell would take far more work than
+/// the pixels they cover.
+pub const MAX_QUAD_CELLS: u64 = 2048 * 2048;
+
+/// Every generation kept for rewinding is a buffer of its own.
+const MAX_HISTORY: u32 = 4096;
+
+/// The compute kernel stepping totalistic rules on the unpacked layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Kernel {
+    /// `compute_main`: each invocation reads its nine cells from storage.
+    #[default]
+    Naive,
+    /// `compute_tiled`: each workgroup loads its cells into workgroup memory once.
+    Tiled,
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 2] = [Kernel::Naive, Kernel::Tiled];
+}
+
+impl FromStr for Kernel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "naive" => Ok(Kernel::Naive),
+            "tiled" => Ok(Kernel::Tiled),
+            _ => Err(format!("unknown kernel {s:?}, expected naive or tiled")),
+        }
+    }
+}
+
+impl fmt::Display for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kernel::Naive => "naive",
+            Kernel::Tiled => "tiled",
+        })
+    }
+}
+
+/// How the window draws the grid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Renderer {
+    /// An instanced quad for every cell, collapsed for dead cells.
+    #[default]
+    Quads,
+    /// A single triangle covering the window, whose fragment shader reads the
+    /// cell under each pixel from the cell buffer.
+    Fullscreen,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quads" => Ok(Renderer::Quads),
+            "fullscreen" => Ok(Renderer::Fullscreen),
+            _ => Err(format!("unknown renderer {s:?}, expected quads or fullscreen")),
+        }
+    }
+}
+
+impl fmt::Display for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Renderer::Quads => "quads",
+            Renderer::Fullscreen => "fullscreen",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub automaton: Automaton,
+    pub generator: Generator,
+    pub seed: u64,
+    /// Probability of a cell being alive in random soups.
+    pub density: f64,
+    /// Side of the square soups of `Generator::Blob` and `Generator::Symmetric`.
+    pub soup_size: u32,
+    pub pattern: Option<Pattern>,
+    /// Where the top left corner of the pattern goes, as (column, row from the top).
+    pub pattern_at: Option<(u32, u32)>,
+    pub boundary: Boundary,
+    /// The width and height of the grid in cells.
+    pub grid_size: (u32, u32),
+    pub packed: bool,
+    pub kernel: Kernel,
+    pub renderer: Renderer,
+    /// Whether the fullscreen renderer averages the cells sharing a pixel.
+    pub smooth: bool,
+    pub colors: ColorMode,
+    /// The palettes given, drawn with the first.
+    pub palettes: Vec<Palette>,
+    pub generations_per_second: f64,
+    pub frames_per_second: f64,
+    /// Number of generations the window keeps for rewinding.
+    pub history: u32,
+    /// Number of generations to benchmark instead of opening a window.
+    pub benchmark: Option<u32>,
+    /// Number of generations to run without a window before saving the last one.
+    pub headless: Option<u64>,
+    /// The pattern file `headless` saves to.
+    pub output: Option<String>,
+    /// The CSV file the population history is written to.
+    pub population: Option<String>,
+    pub on_stable: OnStable,
+    pub fallback_adapter: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            automaton: Automaton::default(),
+            generator: Generator::default(),
+            seed: 0,
+            density: 0.4,
+            soup_size: 16,
+            pattern: None,
+            pattern_at: None,
+            boundary: Boundary::default(),
+            grid_size: (128, 128),
+            packed: false,
+            kernel: Kernel::default(),
+            renderer: Renderer::default(),
+            smooth: false,
+            colors: ColorMode::default(),
+            palettes: Vec::new(),
+            generations_per_second: 5.0,
+            frames_per_second: 60.0,
+            history: 64,
+            benchmark: None,
+            headless: None,
+            output: None,
+            population: None,
+            on_stable: OnStable::default(),
+            fallback_adapter: false,
+        }
+    }
+}
+
+/// Options that take no value, and are turned on with `true` in a config file.
+const FLAGS: [&str; 3] = ["packed", "smooth", "fallback-adapter"];
+
+/// Options of a config file naming files to read, which like a `.rule` file
+/// are found relative to the config file.
+const PATHS: [&str; 2] = ["pattern", "palette"];
+
+/// Turns the text of a config file in `dir` into the arguments it stands for,
+/// and the rule it names, if any. Values are written bare or in double
+/// quotes, and options given several times, such as `palette`, take an array
+/// of them.
+fn config_args(text: &str, dir: &Path) -> Result<(Vec<String>, Option<String>), String> {
+    let (mut args, mut rule) = (Vec::new(), None);
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| format!("line {}: {message}", index + 1);
+        let line = palette::strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("expected option = value, found {line:?}")))?;
+        let (key, value) = (key.trim(), value.trim());
+        let mut values = match value.starts_with(['"', '[']) {
+            true => palette::parse_value(value).map_err(error)?,
+            false => vec![value.to_string()],
+        };
+        let in_dir = |path: &str| dir.join(path).to_string_lossy().into_owned();
+        if PATHS.contains(&key) {
+            values = values.iter().map(|path| in_dir(path)).collect();
+        }
+        match key {
+            "rule" if value.ends_with(".rule") => rule = Some(in_dir(&values.concat())),
+            "rule" => rule = Some(values.concat()),
+            "config" | "help" => return Err(error(format!("{key} cannot be set in a config file"))),
+            _ if FLAGS.contains(&key) => match value {
+                "true" => args.push(format!("--{key}")),
+                "false" => (),
+                _ => return Err(error(format!("{key} must be true or false, found {value:?}"))),
+            },
+            _ => {
+                for value in values {
+                    args.extend([format!("--{key}"), value]);
+                }
+            }
+        }
+    }
+    Ok((args, rule))
+}
+
+/// Parses a grid size, either `N` for a square grid or `WxH`.
+fn parse_grid_size(size: &str) -> Result<(u32, u32), String> {
+    let side = |side: &str| match side.trim().parse() {
+        Ok(n) if (1..=MAX_GRID_SIZE).contains(&n) => Some(n),
+        _ => None,
+    };
+    let parsed = match size.split_once(['x', 'X']) {
+        Some((width, height)) => side(width).zip(side(height)),
+        None => side(size).map(|n| (n, n)),
+    };
+    parsed.ok_or_else(|| format!("invalid grid size {size:?}, expected N or WxH with sides of 1 to {MAX_GRID_SIZE}"))
+}
+
+/// Parses a rate per second within `range`.
+fn parse_rate(name: &str, value: &str, range: RangeInclusive<f64>) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if range.contains(&rate) => Ok(rate),
+        _ => Err(format!(
+            "invalid value {value:?} for {name}, expected a number from {} to {}",
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut rule = None;
+        let (mut generator, mut seed) = (None, None);
+        let mut renderer = None;
+
+        // The options of a config file come first, so that the command line overrides them.
+        let args: Vec<String> = args.into_iter().collect();
+        let (mut config, mut config_rule) = (Vec::new(), None);
+        if args.iter().filter(|arg| *arg == "--config").count() > 1 {
+            return Err("--config can only be given once".to_string());
+        }
+        if let Some(at) = args.iter().position(|arg| arg == "--config") {
+            let path = args.get(at + 1).ok_or("--config needs a value")?;
+            let text = fs::read_to_string(path).map_err(|e| format!("cannot read {path:?}: {e}"))?;
+            let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+            (config, config_rule) = config_args(&text, dir).map_err(|e| format!("in {path:?}, {e}"))?;
+        }
+        let mut args = config.into_iter().chain(args);
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("{name} needs a value"))
+            };
+            match arg.as_str() {
+                "--init" => generator = Some(value("--init")?.parse()?),
+                "--seed" => {
+                    let value = value("--seed")?;
+                    seed = Some(value.parse().map_err(|_| format!("invalid seed {value:?}"))?);
+                }
+                "--density" => {
+                    let density = value("--density")?;
+                    options.density = match density.parse() {
+                        Ok(p) if (0.0..=1.0).contains(&p) => p,
+                        _ => return Err(format!("invalid density {density:?}, expected 0 to 1")),
+                    };
+                }
+                "--soup-size" => {
+                    let size = value("--soup-size")?;
+                    options.soup_size = match size.parse() {
+                        Ok(n) if (1..=MAX_GRID_SIZE).contains(&n) => n,
+                        _ => return Err(format!("invalid soup size {size:?}, expected 1 to {MAX_GRID_SIZE}")),
+                    };
+                }
+                "--pattern" => {
+                    let path = value("--pattern")?;
+                    let pattern = Pattern::load(&path).map_err(|e| format!("cannot load {path:?}: {e}"))?;
+                    options.pattern = Some(pattern);
+                }
+                "--at" => {
+                    let at = value("--at")?;
+                    let parsed = at
+                        .split_once(',')
+                        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+                    options.pattern_at = Some(parsed.ok_or_else(|| format!("invalid position {at:?}, expected X,Y"))?);
+                }
+                "--boundary" => options.boundary = value("--boundary")?.parse()?,
+                "--grid-size" => options.grid_size = parse_grid_size(&value("--grid-size")?)?,
+                "--packed" => options.packed = true,
+                "--kernel" => options.kernel = value("--kernel")?.parse()?,
+                "--renderer" => renderer = Some(value("--renderer")?.parse()?),
+                "--smooth" => options.smooth = true,
+                "--colors" => options.colors = value("--colors")?.parse()?,
+                "--palette" => {
+                    let path = value("--palette")?;
+                    let palette = Palette::load(&path).map_err(|e| format!("cannot load {path:?}: {e}"))?;
+                    options.palettes.push(palette);
+                }
+                "--sps" => options.generations_per_second = parse_rate("--sps", &value("--sps")?, GENERATIONS_PER_SECOND)?,
+                "--fps" => options.frames_per_second = parse_rate("--fps", &value("--fps")?, FRAMES_PER_SECOND)?,
+                "--history" => {
+                    let history = value("--history")?;
+                    options.history = match history.parse() {
+                        Ok(n) if n <= MAX_HISTORY => n,
+                        _ => return Err(format!("invalid history {history:?}, expected 0 to {MAX_HISTORY}")),
+                    };
+                }
+                "--benchmark" => {
+                    let generations = value("--benchmark")?;
+                    options.benchmark = Some(generations.parse().map_err(|_| {
+                        format!("invalid number of generations {generations:?}")
+                    })?);
+                }
+                "--headless" => {
+                    let generations = value("--headless")?;
+                    options.headless = Some(generations.parse().map_err(|_| {
+                        format!("invalid number of generations {generations:?}")
+                    })?);
+                }
+                "--output" => {
+                    let output = value("--output")?;
+                    // Fail now rather than after a long run.
+                    let extension = Path::new(&output).extension().and_then(|e| e.to_str());
+                    Format::from_extension(extension.unwrap_or(""))
+                        .map_err(|e| format!("cannot save {output:?}: {e}"))?;
+                    options.output = Some(output);
+                }
+                "--on-stable" => options.on_stable = value("--on-stable")?.parse()?,
+                "--population" => options.population = Some(value("--population")?),
+                "--fallback-adapter" => options.fallback_adapter = true,
+                // Read before the other options.
+                "--config" => {
+                    value("--config")?;
+                }
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                _ if arg.starts_with('-') => return Err(format!("unknown option {arg:?}")),
+                _ if rule.is_none() => rule = Some(arg),
+                _ => return Err(format!("unexpected argument {arg:?}")),
+            }
+        }
+
+        // Without a rule on the command line or in the config file, run the
+        // rule the pattern was made for.
+        let rule = rule.or(config_rule).or_else(|| options.pattern.as_ref()?.rule.clone());
+        if let Some(rule) = rule {
+            options.automaton = Automaton::from_spec(&rule)
+                .map_err(|e| format!("cannot load rule {rule:?}: {e}"))?;
+        }
+
+        if let Some(pattern) = &options.pattern {
+            let states = options.automaton.states();
+            if pattern.max_state() >= states {
+                return Err(format!(
+                    "the pattern uses state {}, but rule {} has only {states} states",
+                    pattern.max_state(),
+                    options.automaton,
+                ));
+            }
+        }
+
+        options.generator = match (generator, &options.pattern) {
+            (None, None) => Generator::Random,
+            (None | Some(Generator::Pattern), Some(_)) => Generator::Pattern,
+            (Some(Generator::Pattern), None) => return Err("--init pattern needs --pattern".to_string()),
+            (Some(_), Some(_)) => return Err("--pattern needs --init pattern".to_string()),
+            (Some(generator), None) => generator,
+        };
+        // A fresh seed for every run, unless asked to repeat one.
+        options.seed = seed.unwrap_or_else(rand::random);
+
+        if options.packed {
+            if !matches!(options.automaton, Automaton::Totalistic(rule) if rule.states == 2) {
+                return Err("--packed only supports Life-like rules with two states".to_string());
+            }
+            if !options.grid_size.0.is_multiple_of(32) {
+                return Err("--packed needs a grid width that is a multiple of 32".to_string());
+            }
+        }
+        if options.kernel == Kernel::Tiled && (options.packed || options.is_table()) {
+            return Err("--kernel only applies to totalistic rules without --packed".to_string());
+        }
+        let (width, height) = options.grid_size;
+        options.renderer = renderer.unwrap_or(if u64::from(width) * u64::from(height) > MAX_QUAD_CELLS {
+            Renderer::Fullscreen
+        } else {
+            Renderer::Quads
+        });
+        if options.smooth && options.renderer != Renderer::Fullscreen {
+            return Err("--smooth needs --renderer fullscreen".to_string());
+        }
+        if options.headless.is_some() && options.benchmark.is_some() {
+            return Err("--headless and --benchmark cannot be combined".to_string());
+        }
+        if let (Some(output), None) = (&options.output, options.headless) {
+            return Err(format!("--output {output:?} needs --headless"));
+        }
+        if options.on_stable == OnStable::Reseed && !options.generator.is_random() {
+            return Err("--on-stable reseed needs a random --init generator".to_string());
+        }
+        if let (Some(population), Some(_)) = (&options.population, options.benchmark) {
+            return Err(format!("--population {population:?} cannot be combined with --benchmark"));
+        }
+        Ok(options)
+    }
+
+    /// Whether the window counts the population of every generation, rather
+    /// than only of those drawn. Acting on the world settling needs every one,
+    /// and so does writing them all with `--population`; at high speeds the
+    /// counts cost more than the steps.
+    pub fn census_every_generation(&self) -> bool {
+        self.on_stable != OnStable::Log || self.population.is_some()
+    }
+
+    fn is_table(&self) -> bool {
+        matches!(self.automaton, Automaton::Table(_))
+    }
+
+    /// The compute shader entry point that steps the automaton with these options.
+    pub fn entry_point(&self) -> &'static str {
+        if self.packed {
+            "compute_packed"
+        } else if self.is_table() {
+            "compute_table"
+        } else {
+            match self.kernel {
+                Kernel::Naive => "compute_main",
+                Kernel::Tiled => "compute_tiled",
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn config_files_stand_for_long_options() {
+        let text = "# A reproducible run\nrule = \"B36/S23\"\nseed = 42 # the one from last time\ndensity = 0.25\n\
+                    packed = true\nsmooth = false\npalette = [\"a.toml\", \"b.toml\"]\n";
+        let (config, rule) = config_args(text, Path::new("")).unwrap();
+        assert_eq!(config, args("--seed 42 --density 0.25 --packed --palette a.toml --palette b.toml"));
+        assert_eq!(rule.as_deref(), Some("B36/S23"));
+
+        assert_eq!(config_args("seed 42", Path::new("")).unwrap_err(), "line 1: expected option = value, found \"seed 42\"");
+        assert!(config_args("\npacked = yes", Path::new("")).unwrap_err().starts_with("line 2:"));
+        assert!(config_args("config = other.conf", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn config_files_name_files_relative_to_themselves() {
+        let text = "rule = rules/Life.rule\npattern = glider.rle\npalette = [\"a.toml\", \"/b.toml\"]\n\
+                    population = counts.csv\n";
+        let (config, rule) = config_args(text, Path::new("runs")).unwrap();
+        assert_eq!(
+            config,
+            args("--pattern runs/glider.rle --palette runs/a.toml --palette /b.toml --population counts.csv")
+        );
+        assert_eq!(rule.as_deref(), Some("runs/rules/Life.rule"));
+        assert_eq!(config_args("rule = B3/S23", Path::new("runs")).unwrap().1.as_deref(), Some("B3/S23"));
+    }
+
+    #[test]
+    fn the_command_line_overrides_the_config_file() {
+        let path = std::env::temp_dir().join(format!("first_wgpu_app-{}.conf", std::process::id()));
+        fs::write(&path, "rule = B36/S23\nseed = 42\ndensity = 0.25\ngrid-size = 64\n").unwrap();
+        let config = path.to_str().unwrap();
+
+        let options = Options::parse(args(&format!("--seed 7 --config {config} B2/S"))).unwrap();
+        assert_eq!((options.seed, options.density, options.grid_size), (7, 0.25, (64, 64)));
+        assert_eq!(options.automaton.to_string(), "B2/S");
+        let options = Options::parse(args(&format!("--config {config}"))).unwrap();
+        assert_eq!((options.seed, options.automaton.to_string()), (42, "B36/S23".to_string()));
+        assert!(Options::parse(args(&format!("--config {config} --config {config}"))).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+//! How the window colors cells.
+//!
+//! Besides the state of each cell, windows keep track of how long it has been
+//! alive, how recently it changed and how many active neighbors it has, in an
+//! activity buffer updated by `track_cells` in `c_shader.wgsl` after every
+//! generation. The color mode picks which of them `shader.wgsl` shows.
+use std::{fmt, str::FromStr};
+
+/// The oldest age tracked, older cells stay at it.
+pub const MAX_AGE: u32 = 0xffff;
+/// The heat of a cell that changed in the last generation.
+pub const MAX_HEAT: u32 = 0xfff;
+/// The fraction of its heat a cell keeps every generation, rounding down.
+pub const HEAT_DECAY: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// A gradient across the grid, or a color per state for Generations
+    /// rules and rule tables.
+    #[default]
+    Position,
+    /// From newborn to long-lived cells.
+    Age,
+    /// Where cells changed state recently, fading out over the following
+    /// generations. Dead cells glow too.
+    Heat,
+    /// The number of active neighbors, as the rule counts them.
+    Neighbors,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 4] = [ColorMode::Position, ColorMode::Age, ColorMode::Heat, ColorMode::Neighbors];
+
+    /// The value identifying this mode in the `Coloring` uniform of `shader.wgsl`.
+    pub fn shader_value(&self) -> u32 {
+        *self as u32
+    }
+
+    /// Whether the mode shows the activity buffer.
+    pub fn needs_activity(&self) -> bool {
+        *self != ColorMode::Position
+    }
+
+    /// The mode after this one, going back to the first after the last.
+    pub fn next(&self) -> ColorMode {
+        ColorMode::ALL[(*self as usize + 1) % ColorMode::ALL.len()]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Position => "position",
+            ColorMode::Age => "age",
+            ColorMode::Heat => "heat",
+            ColorMode::Neighbors => "neighbors",
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ColorMode::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<&str> = ColorMode::ALL.iter().map(ColorMode::name).collect();
+                format!("unknown color mode {s:?}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What the activity buffer holds about a cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CellActivity {
+    /// The number of generations the cell has been non-dead, up to `MAX_AGE`.
+    pub age: u32,
+    /// `MAX_HEAT` if the cell changed state in the last generation,
+    /// `HEAT_DECAY` times less for every generation since.
+    pub heat: u32,
+    /// The number of neighbors in state 1.
+    pub neighbors: u32,
+}
+
+impl CellActivity {
+    /// Decodes a word of the activity buffer: the age in bits 0 to 15, the
+    /// neighbors in bits 16 to 19, and the heat in bits 20 to 31.
+    pub fn unpack(word: u32) -> Self {
+        CellActivity {
+            age: word & MAX_AGE,
+            heat: word >> 20,
+            neighbors: (word >> 16) & 0xf,
+        }
+    }
+}
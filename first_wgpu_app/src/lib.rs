@@ -0,0 +1,16 @@
+//! Conway's Game of Life and other cellular automata, simulated in compute shaders.
+pub mod boundary;
+pub mod camera;
+pub mod cells;
+pub mod cli;
+pub mod coloring;
+pub mod pacing;
+pub mod palette;
+pub mod pattern;
+pub mod population;
+pub mod reference;
+pub mod rule;
+pub mod seed;
+pub mod stability;
+pub mod table;
+pub mod world;
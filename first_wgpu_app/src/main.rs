@@ -1,21 +1,30 @@
 use std::{
     sync::Arc, 
-    borrow::Cow, 
     mem,
-    time::{Duration, Instant},
+    time::Instant,
 };
 
-use rand::Rng;
-
 use winit::{
     application::ApplicationHandler,
-    event::WindowEvent,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
     window::{Window, WindowId},
 };
 
-#[allow(unused_imports)]
-use wgpu::{core::pipeline, util::DeviceExt};
+use first_wgpu_app::{
+    camera::Camera,
+    cells,
+    cli::{Kernel, Options},
+    pacing::{FrameScheduler, SimulationClock},
+    palette::Palette,
+    pattern,
+    population::PopulationCounter,
+    rule::Automaton,
+    stability::{OnStable, Stabilization, StabilityDetector, MAX_PERIOD},
+    world::{self, request_device, World},
+};
 
 struct State {
     window: Arc<Window>,
@@ -27,23 +36,13 @@ struct State {
 }
 
 impl State {
-    async fn new(window: Arc<Window>) -> State {
+    async fn new(window: Arc<Window>, force_fallback_adapter: bool) -> State {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor::default(),
-                None, // Trace path
-            )
-            .await
-            .unwrap();
 
         let size = window.inner_size();
 
         let surface = instance.create_surface(window.clone()).unwrap();
+        let (adapter, device, queue) = request_device(&instance, Some(&surface), force_fallback_adapter).await;
         let cap = surface.get_capabilities(&adapter);
         let surface_format = cap.formats[0];
 
@@ -87,254 +86,11 @@ impl State {
         // reconfigure the surface
         self.configure_surface();
     }
-}
-
-#[allow(dead_code)]
-struct World {
-    vertex_buf: Option<wgpu::Buffer>,
-    num_vertices: usize,
-    // index_buf: wgpu::Buffer,
-    // index_count: usize,
-    // uniform_buffs: Vec<wgpu::Buffer>,
-    // storage_buffs: Vec<wgpu::Buffer>,
-    grid_size: u32,
-    bind_groups: Vec<wgpu::BindGroup>,
-    render_pipeline: Option<wgpu::RenderPipeline>,
-    compute_pipeline: Option<wgpu::ComputePipeline>,
-}
-
-impl World {
-    fn new(
-        // config: &wgpu::SurfaceConfiguration,
-        surface_format: &wgpu::TextureFormat,
-        // _adapter: &wgpu::Adapter,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-    ) -> Self {
-        let grid_size: u32 = 128;
-
-        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Grid uniforms"),
-            contents: bytemuck::cast_slice(&[grid_size as f32, grid_size as f32]),
-            usage: wgpu::BufferUsages::UNIFORM, // | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let vertices: &[f32] = &[
-        //   X,    Y,
-            -0.8, -0.8, // Triangle 1 (Blue)
-            0.8, -0.8,
-            0.8,  0.8,
-        
-            -0.8, -0.8, // Triangle 2 (Red)
-            0.8,  0.8,
-            -0.8,  0.8,
-        ];
-
-        // let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        //     label: Some("Cell vertices"),
-        //     contents: bytemuck::cast_slice(&vertices),
-        //     usage: wgpu::BufferUsages::VERTEX,
-        // });
-        let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Cell vertices"),
-            size: mem::size_of_val(vertices) as u64,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        queue.write_buffer(&vertex_buf, 0, bytemuck::cast_slice(vertices));
-
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: 2 * std::mem::size_of::<f32>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
-        };
-
-        // An array representing the active state of each cell.
-        let mut cell_state_array: Vec<u32> = vec![0; (grid_size * grid_size) as usize];
-
-        let cell_state_storage = [device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Cell state A"),
-            size: mem::size_of_val(&cell_state_array[..]) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        }),
-        device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Cell state B"),
-            size: mem::size_of_val(&cell_state_array[..]) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        })];
-
-        // for i in (0..cell_state_array.len()).step_by(3) {
-        //     cell_state_array[i] = 1;
-        // } 
-        let mut rng = rand::rng();
-        for i in 0..cell_state_array.len() {
-            cell_state_array[i] = if rng.random::<f64>() > 0.6 { 1 } else { 0 };
-        }
-        queue.write_buffer(&cell_state_storage[0], 0, bytemuck::cast_slice(&cell_state_array[..]));
- 
-        queue.write_buffer(&cell_state_storage[1], 0, bytemuck::cast_slice(&cell_state_array[..]));
-
-        // let cell_shader_module = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-        let cell_shader_module = device.create_shader_module(
-            wgpu::ShaderModuleDescriptor {
-                label: Some("Cell shaders"),
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-            }
-        );
-
-        let simulation_shader_module = device.create_shader_module(
-            wgpu::ShaderModuleDescriptor {
-                label: Some("Game of Life simulation shader"),
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("c_shader.wgsl"))),
-            }
-        );
-
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Cell Bind Group Layout"),
-            entries: &[
-                // Binding 0: Uniform buffer (grid uniform)
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Binding 1: Read-only storage buffer (cell state input)
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Binding 2: Storage buffer (cell state output)
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        });
-
-        let bind_groups = vec![
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("Cell renderer bind group A"),
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: uniform_buf.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: cell_state_storage[0].as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: cell_state_storage[1].as_entire_binding(),
-                    },
-                ],
-            }),
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("Cell renderer bind group B"),
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: uniform_buf.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: cell_state_storage[1].as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: cell_state_storage[0].as_entire_binding(),
-                    },
-                ],
-            })
-        ];
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Cell pipeline layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-        
-        let cell_pipeline = device.create_render_pipeline(
-            &wgpu::RenderPipelineDescriptor {
-                label: Some("Cell pipeline"),
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &cell_shader_module,
-                    entry_point: Some("vertex_main"), //can be None because only 1
-                    buffers: &[vertex_buffer_layout],
-                    compilation_options: Default::default(),    
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &cell_shader_module,
-                    entry_point: Some("fragment_main"), //can be None because only 1
-                    // targets: &[Some(surface_format.clone().into())],
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: surface_format.clone(),
-                        blend: None, // or another blend configuration
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                    compilation_options: Default::default(),    
-                }),
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-                cache: None,
-        });
-
-        let simulation_pipeline = device.create_compute_pipeline(
-            &wgpu::ComputePipelineDescriptor {
-                label: Some("Simulation pipeline"),
-                layout: Some(&pipeline_layout),
-                module: &simulation_shader_module,
-                entry_point: Some("compute_main"),
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                cache: None,
-            }
-        );
-
-        Self {
-            vertex_buf: Some(vertex_buf),
-            num_vertices: vertices.len() / 2,
-            grid_size: grid_size,
-            // uniform_buffs: uniform_buf, //This is only a handle to the actual buffer
-            // storage_buffs: cell_state_storage,
-            bind_groups: bind_groups,
-            render_pipeline: Some(cell_pipeline),
-            compute_pipeline: Some(simulation_pipeline),
-        }
-    }
 
-    fn render(&self, state: &mut State, frame_idx: usize) {
+    /// Draws the current generation of `world` to the window.
+    fn render(&mut self, world: &mut World) {
         // Create texture view
-        let surface_texture = state
+        let surface_texture = self
             .surface
             .get_current_texture()
             .expect("failed to acquire next swapchain texture");
@@ -343,76 +99,332 @@ impl World {
             .create_view(&wgpu::TextureViewDescriptor {
                 // Without add_srgb_suffix() the image we will be working with
                 // might not be "gamma correct".
-                format: Some(state.surface_format.add_srgb_suffix()),
+                format: Some(self.surface_format.add_srgb_suffix()),
                 ..Default::default()
             });
 
-        // Renders a GREEN screen
-        let mut encoder = state.device.create_command_encoder(&Default::default());
-        //###########################3
-        let mut compute_pass = encoder.begin_compute_pass(&Default::default());
-        
-        compute_pass.set_pipeline(self.compute_pipeline.as_ref().unwrap());
-        compute_pass.set_bind_group(0, &self.bind_groups[frame_idx], &[]);
-
-        let workgroup_count = self.grid_size.div_ceil(8);
-        compute_pass.dispatch_workgroups(workgroup_count, workgroup_count, 1);
-
-        drop(compute_pass);
-
-        // Create the renderpass which will clear the screen.
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &texture_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    // load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
-                    load: wgpu::LoadOp::Clear(wgpu::Color {r: 0., g: 0., b: 0.3, a: 1.}),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-
-        // If you wanted to call any drawing commands, they would go here.
-        render_pass.set_pipeline(self.render_pipeline.as_ref().unwrap());
-        render_pass.set_vertex_buffer(0, self.vertex_buf.as_ref().unwrap().slice(..));
-        
-        render_pass.set_bind_group(0, &self.bind_groups[frame_idx], &[]);
-        render_pass.draw(
-            0..self.num_vertices as u32, 
-            0..(self.grid_size * self.grid_size)
-        );
-
-        // End the renderpass.
-        drop(render_pass);
-
-        // Submit the command in the queue to execute
-        state.queue.submit([encoder.finish()]);
+        world.render(&self.device, &self.queue, &texture_view);
         surface_texture.present();
     }
 }
 
-const TARGET_FPS: u32 = 5; // Desired frames per second
 
-#[derive(Default)]
 struct App {
     state: Option<State>,
     world: Option<World>,
-    frame_counter: usize,
-    frame_duration: Duration,
+    options: Options,
+    frames: FrameScheduler,
+    clock: SimulationClock,
+    /// Generations requested with the step key, simulated on the next frame.
+    single_steps: u32,
+    cursor: Option<PhysicalPosition<f64>>,
+    /// The state painted while a mouse button is held: 1 for the left button, 0 for the right.
+    painting: Option<u32>,
+    /// The last cell painted during the current drag.
+    last_painted: Option<(u32, u32)>,
+    population: PopulationCounter,
+    stability: StabilityDetector,
+    camera: Camera,
+    /// Whether the middle mouse button is held, dragging the view along.
+    panning: bool,
+    /// Whether the generation on screen changed without a step, and needs a
+    /// census of its own: at the start, and after painting or reseeding.
+    census_due: bool,
+    /// Whether the user was told that settling goes unnoticed while frames
+    /// skip generations.
+    warned_skipping: bool,
 }
 
 impl App {
-    fn new() -> Self {
-        Self{frame_duration: Duration::from_secs_f64(1.0 / TARGET_FPS as f64), ..Default::default()}
+    fn new(options: Options) -> Self {
+        Self {
+            state: None,
+            world: None,
+            frames: FrameScheduler::new(options.frames_per_second, Instant::now()),
+            clock: SimulationClock::new(options.generations_per_second),
+            single_steps: 0,
+            cursor: None,
+            painting: None,
+            last_painted: None,
+            population: population_counter(&options),
+            stability: StabilityDetector::new(0),
+            census_due: true,
+            warned_skipping: false,
+            camera: Camera::fit(options.grid_size, PhysicalSize::new(1, 1)),
+            panning: false,
+            options,
+        }
+    }
+}
+
+/// Speed keys multiply or divide the generations per second by this factor.
+const SPEED_FACTOR: f64 = 2.0;
+/// Each notch of the mouse wheel scales cells by this factor.
+const ZOOM_FACTOR: f64 = 1.25;
+/// Touchpads scroll by pixels rather than notches, this many to a notch.
+const PIXELS_PER_NOTCH: f64 = 50.0;
+
+impl App {
+    /// Shows the rule, the generation on screen, its population once counted,
+    /// and the speed in the window title.
+    fn update_title(&self) {
+        let (Some(state), Some(world)) = (&self.state, &self.world) else {
+            return;
+        };
+        let speed = if self.clock.is_paused() {
+            "paused".to_string()
+        } else {
+            format!("{} gen/s", self.clock.generations_per_second())
+        };
+        // The count arrives a frame or so after the generation is drawn.
+        let population = match self.population.latest() {
+            Some((_, population)) => format!(" - population {population}"),
+            None => String::new(),
+        };
+        let newest = *world.history().end();
+        let rewound = if newest > world.generation() {
+            format!(" (rewound from {newest})")
+        } else {
+            String::new()
+        };
+        state.get_window().set_title(&format!(
+            "Game of Life - {} - generation {}{rewound}{population} - {speed}",
+            self.options.automaton, world.generation(),
+        ));
+    }
+
+    /// Waits for the population counts still on the GPU, and writes the
+    /// rest of the history to the `--population` file, if any.
+    fn write_population(&mut self) {
+        let Some(path) = &self.options.population else {
+            return;
+        };
+        if let Some(state) = &self.state {
+            state.device.poll(wgpu::Maintain::Wait);
+            self.population.collect(&state.device);
+        }
+        match self.population.finish() {
+            Ok(()) => println!("Saved the population history to {path}"),
+            Err(e) => eprintln!("Cannot save {path}: {e}"),
+        }
+    }
+
+    /// Takes in the censuses that are ready, reporting and acting on the
+    /// world settling, and shows the latest population.
+    fn collect_censuses(&mut self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let censuses = self.population.collect(&state.device);
+        for census in &censuses {
+            let Some(stabilization) = self.stability.observe(census) else {
+                continue;
+            };
+            report(&self.options, &stabilization);
+            match self.options.on_stable {
+                OnStable::Log => (),
+                OnStable::Pause => self.clock.set_paused(true),
+                OnStable::Reseed => {
+                    let (Some(state), Some(world)) = (&self.state, &mut self.world) else {
+                        return;
+                    };
+                    reseed(world, &state.device, &state.queue, &mut self.options, &mut self.population, &mut self.stability);
+                    self.census_due = true;
+                    // The remaining censuses are from the previous soup.
+                    break;
+                }
+            }
+        }
+        if let (Some(world), Some(path)) = (&self.world, &self.options.population) {
+            // Generations older than the history cannot be counted again.
+            if let Err(e) = self.population.write_before(*world.history().start()) {
+                eprintln!("Cannot save {path}: {e}");
+            }
+        }
+        if !censuses.is_empty() {
+            self.update_title();
+        }
+    }
+
+    /// Reads back the generation on screen and writes it to an RLE file.
+    fn save_pattern(&self) {
+        let (Some(state), Some(world)) = (&self.state, &self.world) else {
+            return;
+        };
+        let snapshot = world.read_cells(&state.device, &state.queue);
+        let path = format!("generation-{}.rle", snapshot.generation);
+        match export(&snapshot, &self.options.automaton, &path) {
+            Ok(()) => println!(
+                "Saved generation {} with {} live cells to {path}",
+                snapshot.generation,
+                snapshot.population()
+            ),
+            Err(e) => eprintln!("Cannot save {path}: {e}"),
+        }
+    }
+
+    /// Shows the grid through the camera as it is now.
+    fn update_camera(&self) {
+        let (Some(state), Some(world)) = (&self.state, &self.world) else {
+            return;
+        };
+        world.set_camera(&state.queue, &self.camera, state.size);
+        state.get_window().request_redraw();
+    }
+
+    /// Resizes the grid to `width` by `height` cells, keeping the cells in its
+    /// middle, and shows the whole of it.
+    fn resize_grid(&mut self, (width, height): (u32, u32)) {
+        let (Some(state), Some(world)) = (&self.state, &mut self.world) else {
+            return;
+        };
+        if let Err(e) = world.resize_grid(&state.device, &state.queue, width, height) {
+            eprintln!("Cannot resize the grid: {e}");
+            return;
+        }
+        let size = (width, height);
+        println!("Resized the grid to {width}x{height}");
+        // Reseeding fills a grid of the new size.
+        self.options.grid_size = size;
+        self.options.renderer = world.renderer();
+        self.camera = Camera::fit(size, state.size);
+        self.census_due = true;
+        self.update_camera();
+    }
+
+    /// Pauses and moves `offset` generations through the history, as far as
+    /// it goes. Going forward past the newest generation simulates it.
+    fn scrub(&mut self, offset: i64) {
+        self.clock.set_paused(true);
+        let (Some(state), Some(world)) = (&self.state, &mut self.world) else {
+            return;
+        };
+        let history = world.history();
+        let target = world.generation().saturating_add_signed(offset);
+        if target > *history.end() {
+            self.single_steps += (target - history.end()) as u32;
+        }
+        let target = target.clamp(*history.start(), *history.end());
+        if target != world.generation() {
+            world.rewind(&state.device, &state.queue, target);
+            self.census_due = true;
+        }
+    }
+
+    /// Space pauses and resumes, N steps a single generation, the arrow keys
+    /// move through the history, + and - change the speed, [ and ] resize the
+    /// grid, Home shows all of it, C changes the colors, P the palette, and S
+    /// saves the current generation.
+    fn handle_key(&mut self, key: &Key) {
+        match key {
+            Key::Named(NamedKey::ArrowLeft) => self.scrub(-1),
+            Key::Named(NamedKey::ArrowRight) => self.scrub(1),
+            Key::Character(c) if c == "]" => {
+                let (width, height) = self.options.grid_size;
+                self.resize_grid((width.saturating_mul(2), height.saturating_mul(2)));
+                return;
+            }
+            Key::Character(c) if c == "[" => {
+                let (width, height) = self.options.grid_size;
+                self.resize_grid((width / 2, height / 2));
+                return;
+            }
+            Key::Named(NamedKey::Home) => {
+                if let Some(state) = &self.state {
+                    self.camera = Camera::fit(self.options.grid_size, state.size);
+                    self.update_camera();
+                }
+                return;
+            }
+            Key::Character(c) if c.eq_ignore_ascii_case("s") => {
+                self.save_pattern();
+                return;
+            }
+            Key::Character(c) if c.eq_ignore_ascii_case("c") => {
+                let (Some(state), Some(world)) = (&self.state, &mut self.world) else {
+                    return;
+                };
+                let mode = world.color_mode().next();
+                match world.set_color_mode(&state.device, &state.queue, mode) {
+                    Ok(()) => println!("Coloring cells by {mode}"),
+                    Err(e) => eprintln!("Cannot switch colors: {e}"),
+                }
+            }
+            Key::Character(c) if c.eq_ignore_ascii_case("p") => {
+                let (Some(state), Some(world)) = (&self.state, &mut self.world) else {
+                    return;
+                };
+                if self.options.palettes.is_empty() {
+                    eprintln!("No palette to switch to, load some with --palette");
+                    return;
+                }
+                // The palettes given in order, then the built in one.
+                let palettes = &self.options.palettes;
+                let next = match palettes.iter().position(|palette| palette == world.palette()) {
+                    Some(i) if i + 1 < palettes.len() => palettes[i + 1].clone(),
+                    Some(_) => Palette::default(),
+                    None => palettes[0].clone(),
+                };
+                println!("Drawing with the {} palette", next.name);
+                world.set_palette(&state.queue, next);
+            }
+            Key::Named(NamedKey::Space) => {
+                self.clock.set_paused(!self.clock.is_paused());
+            }
+            Key::Character(c) if c.eq_ignore_ascii_case("n") => {
+                // Stepping implies pausing, otherwise the step would be lost among the others.
+                self.clock.set_paused(true);
+                self.single_steps += 1;
+            }
+            Key::Character(c) if c == "+" || c == "=" => {
+                let speed = self.clock.generations_per_second() * SPEED_FACTOR;
+                self.clock.set_generations_per_second(speed);
+            }
+            Key::Character(c) if c == "-" => {
+                let speed = self.clock.generations_per_second() / SPEED_FACTOR;
+                self.clock.set_generations_per_second(speed);
+            }
+            _ => return,
+        }
+        self.update_title();
+        if let Some(state) = &self.state {
+            state.get_window().request_redraw();
+        }
+    }
+
+    /// Paints the cell under the cursor if a mouse button is held, connecting
+    /// it to the previously painted cell.
+    fn paint(&mut self, position: PhysicalPosition<f64>) {
+        let (Some(cell_state), Some(state), Some(world)) = (self.painting, &self.state, &mut self.world) else {
+            return;
+        };
+        let Some(cell) = world.cell_at(self.camera.to_cells(position, state.size)) else {
+            self.last_painted = None;
+            return;
+        };
+        world.paint_line(&state.device, &state.queue, self.last_painted.unwrap_or(cell), cell, cell_state);
+        self.last_painted = Some(cell);
+        self.census_due = true;
     }
 }
 
 impl ApplicationHandler for App {
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        // The event loop woke up because the next frame is due.
+        if let StartCause::ResumeTimeReached { .. } = cause {
+            if let Some(state) = &self.state {
+                state.get_window().request_redraw();
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        self.collect_censuses();
+        // Sleep until the next frame is due instead of spinning, unless an
+        // event wakes us up earlier.
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.frames.next_frame()));
+    }
+
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         // Create window object
         let window = Arc::new(
@@ -421,59 +433,369 @@ impl ApplicationHandler for App {
                 .unwrap(),
         );
 
-        let state = pollster::block_on(State::new(window.clone()));
+        let state = pollster::block_on(State::new(window.clone(), self.options.fallback_adapter));
         self.state = Some(state);
 
         let state_ref = self.state.as_ref().unwrap();
+        exit_unless_fits(&state_ref.device, &self.options);
         self.world = Some(World::new(
-            &state_ref.surface_format, 
+            Some(&state_ref.surface_format),
             &state_ref.device, 
-            &state_ref.queue
+            &state_ref.queue,
+            &self.options,
         ));
 
-        window.request_redraw();
+        self.camera = Camera::fit(self.options.grid_size, state_ref.size);
+        self.update_camera();
+        self.update_title();
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
         let state = self.state.as_mut().unwrap();
         let world = self.world.as_mut().unwrap();
-        let start = Instant::now();
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
+                self.write_population();
                 event_loop.exit();
             }
             WindowEvent::RedrawRequested => {
-                world.render(state, self.frame_counter%2);
-                self.frame_counter += 1;
-
-                while Instant::now() - start < self.frame_duration {
-                    // Busy-wait loop
+                // Redraws requested by the system, e.g. after a resize, are
+                // drawn without advancing the simulation or the schedule.
+                let now = Instant::now();
+                let generations = if self.frames.is_due(now) {
+                    self.frames.frame_drawn(now);
+                    self.clock.tick(now)
+                } else {
+                    0
+                } + mem::take(&mut self.single_steps);
+                if mem::take(&mut self.census_due) {
+                    take_census(world, &state.device, &state.queue, &mut self.population);
                 }
-
-                // Emits a new redraw requested event.
-                state.get_window().request_redraw();
+                let every_generation = self.options.census_every_generation();
+                let population = every_generation.then_some(&mut self.population);
+                advance(world, &state.device, &state.queue, generations.into(), population);
+                if !every_generation && generations > 0 {
+                    // Only the generation drawn is counted, for the title.
+                    take_census(world, &state.device, &state.queue, &mut self.population);
+                    if generations > 1 && !self.stability.is_settled() && !mem::replace(&mut self.warned_skipping, true) {
+                        eprintln!(
+                            "Only one in {generations} generations is counted at this speed, so the world settling goes \
+                             unnoticed; pass --on-stable or --population to count them all"
+                        );
+                    }
+                }
+                // The frame shows the generation `world.generation()` ends up at.
+                state.render(world);
+                if generations > 0 {
+                    self.update_title();
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { logical_key, state: ElementState::Pressed, .. },
+                ..
+            } => {
+                self.handle_key(&logical_key);
+            }
+            WindowEvent::MouseInput { state: button_state, button: MouseButton::Middle, .. } => {
+                self.panning = button_state == ElementState::Pressed;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let notches = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y.into(),
+                    MouseScrollDelta::PixelDelta(position) => position.y / PIXELS_PER_NOTCH,
+                };
+                // Without a cursor in the window, zoom around its middle.
+                let anchor = self.cursor.unwrap_or_else(|| {
+                    PhysicalPosition::new(f64::from(state.size.width) / 2.0, f64::from(state.size.height) / 2.0)
+                });
+                self.camera.zoom(ZOOM_FACTOR.powf(notches), anchor, state.size, self.options.grid_size);
+                self.update_camera();
+            }
+            WindowEvent::MouseInput { state: button_state, button, .. } => {
+                let cell_state = match button {
+                    MouseButton::Left => 1,
+                    MouseButton::Right => 0,
+                    _ => return,
+                };
+                match button_state {
+                    ElementState::Pressed => {
+                        self.painting = Some(cell_state);
+                        self.last_painted = None;
+                        if let Some(position) = self.cursor {
+                            self.paint(position);
+                        }
+                    }
+                    ElementState::Released if self.painting == Some(cell_state) => {
+                        self.painting = None;
+                    }
+                    ElementState::Released => (),
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (true, Some(previous)) = (self.panning, self.cursor) {
+                    self.camera.pan((position.x - previous.x, position.y - previous.y));
+                    self.update_camera();
+                }
+                self.cursor = Some(position);
+                self.paint(position);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                self.last_painted = None;
             }
             WindowEvent::Resized(size) => {
                 // Reconfigures the size of the surface. We do not re-render
                 // here as this event is always folloed up by redraw request.
                 state.resize(size);
+                // The view keeps its center and zoom, and cells stay square.
+                self.update_camera();
             }
             _ => (),
         }
     }
 }
 
+/// Writes a generation to a pattern file, in the format given by the extension of `path`.
+fn export(snapshot: &cells::Snapshot, automaton: &Automaton, path: &str) -> Result<(), pattern::PatternError> {
+    let mut pattern = snapshot.to_pattern();
+    pattern.rule = Some(automaton.to_string());
+    pattern.comments.push(format!("Generation {}", snapshot.generation));
+    pattern.save(path)
+}
+
+/// Creates a device for running without a window.
+fn headless_device(options: &Options) -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let (adapter, device, queue) =
+        pollster::block_on(request_device(&instance, None, options.fallback_adapter));
+    println!("Running on Adapter: {:?}", adapter.get_info().name);
+    exit_unless_fits(&device, options);
+    (device, queue)
+}
+
+/// A population counter writing to the `--population` file, if any.
+fn population_counter(options: &Options) -> PopulationCounter {
+    let mut population = PopulationCounter::new();
+    if let Some(path) = &options.population {
+        if let Err(e) = population.write_to(path) {
+            eprintln!("Cannot save {path}: {e}");
+            std::process::exit(1);
+        }
+    }
+    population
+}
+
+/// Exits with an error if `device` cannot hold the grid of `options`.
+fn exit_unless_fits(device: &wgpu::Device, options: &Options) {
+    if let Err(e) = world::check_limits(device, options) {
+        eprintln!("{e}");
+        std::process::exit(2);
+    }
+}
+
+/// Records `generations` steps of `world` and submits them, taking a census
+/// of every new generation if given a counter.
+fn advance(
+    world: &mut World,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    generations: u64,
+    mut population: Option<&mut PopulationCounter>,
+) {
+    let mut encoder = device.create_command_encoder(&Default::default());
+    let mut pending = Vec::new();
+    for remaining in (1..=generations).rev() {
+        world.step_within(&mut encoder, remaining);
+        if let Some(population) = population.as_deref_mut() {
+            pending.push(population.record(device, &mut encoder, world));
+        }
+    }
+    queue.submit([encoder.finish()]);
+    if let Some(population) = population {
+        pending.into_iter().for_each(|count| population.submitted(count));
+    }
+}
+
+/// Takes a census of the current generation of `world`.
+fn take_census(world: &World, device: &wgpu::Device, queue: &wgpu::Queue, population: &mut PopulationCounter) {
+    let mut encoder = device.create_command_encoder(&Default::default());
+    let pending = population.record(device, &mut encoder, world);
+    queue.submit([encoder.finish()]);
+    population.submitted(pending);
+}
+
+/// Submitting in batches keeps the command buffers small for long runs.
+const BATCH: u64 = 100;
+
+/// Advances `world` by `generations` and waits for the GPU to finish.
+fn simulate(world: &mut World, device: &wgpu::Device, queue: &wgpu::Queue, generations: u64) {
+    let mut remaining = generations;
+    while remaining > 0 {
+        advance(world, device, queue, BATCH.min(remaining), None);
+        remaining -= BATCH.min(remaining);
+    }
+    device.poll(wgpu::Maintain::Wait);
+}
+
+/// Reports that the world settled, naming the soup it grew from.
+fn report(options: &Options, stabilization: &Stabilization) {
+    if options.generator.is_random() {
+        println!("The {} soup with seed {} {stabilization}", options.generator, options.seed);
+    } else {
+        println!("The world {stabilization}");
+    }
+}
+
+/// Replaces the current generation of `world` with a soup from the next seed,
+/// once the censuses of the previous soup are in.
+fn reseed(
+    world: &mut World,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    options: &mut Options,
+    population: &mut PopulationCounter,
+    stability: &mut StabilityDetector,
+) {
+    device.poll(wgpu::Maintain::Wait);
+    population.collect(device);
+    options.seed = options.seed.wrapping_add(1);
+    world.reseed(queue, options);
+    stability.restart(world.generation());
+    println!("Starting from a {} soup with seed {}", options.generator, options.seed);
+}
+
+/// Times `generations` steps of every applicable kernel on the same grid, without a window.
+fn benchmark(options: &Options, generations: u32) {
+    let (device, queue) = headless_device(options);
+
+    // Only totalistic rules on the unpacked layout have a choice of kernel.
+    let kernels = if options.packed || matches!(options.automaton, Automaton::Table(_)) {
+        vec![options.kernel]
+    } else {
+        Kernel::ALL.to_vec()
+    };
+
+    for kernel in kernels {
+        let options = Options { kernel, history: 0, ..options.clone() };
+        let mut world = World::new(None, &device, &queue, &options);
+
+        // Warm up, so that pipeline compilation and uploads are not timed.
+        simulate(&mut world, &device, &queue, 1);
+
+        let start = Instant::now();
+        simulate(&mut world, &device, &queue, generations.into());
+        let elapsed = start.elapsed();
+
+        println!(
+            "{} kernel: {generations} generations of {}x{} in {:.3}s, {:.1} generations/s",
+            options.entry_point(),
+            options.grid_size.0,
+            options.grid_size.1,
+            elapsed.as_secs_f64(),
+            generations as f64 / elapsed.as_secs_f64(),
+        );
+    }
+}
+
+/// Runs the simulation without a window and writes the last generation to a
+/// pattern file, watching for the world to settle on the way.
+fn headless(options: &Options, generations: u64) {
+    let (device, queue) = headless_device(options);
+    // There is no rewinding without a window.
+    let mut options = Options { history: 0, ..options.clone() };
+    let mut world = World::new(None, &device, &queue, &options);
+    let mut population = population_counter(&options);
+    let mut stability = StabilityDetector::new(0);
+    take_census(&world, &device, &queue, &mut population);
+
+    let start = Instant::now();
+    let mut remaining = generations;
+    'run: while remaining > 0 {
+        advance(&mut world, &device, &queue, BATCH.min(remaining), Some(&mut population));
+        remaining -= BATCH.min(remaining);
+        // Censuses come in while later batches run, which also frees their
+        // buffers for reuse. The last ones are waited for.
+        if remaining == 0 {
+            device.poll(wgpu::Maintain::Wait);
+        }
+        for census in population.collect(&device) {
+            let Some(stabilization) = stability.observe(&census) else {
+                continue;
+            };
+            report(&options, &stabilization);
+            match options.on_stable {
+                OnStable::Log => (),
+                OnStable::Pause => break 'run,
+                OnStable::Reseed if remaining > 0 => {
+                    reseed(&mut world, &device, &queue, &mut options, &mut population, &mut stability);
+                    take_census(&world, &device, &queue, &mut population);
+                    break;
+                }
+                OnStable::Reseed => (),
+            }
+        }
+        if let Some(path) = &options.population {
+            if let Err(e) = population.write_before(world.generation()) {
+                eprintln!("Cannot save {path}: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    let snapshot = world.read_cells(&device, &queue);
+    println!(
+        "Ran {} generations in {:.3}s, {} live cells remain",
+        snapshot.generation,
+        elapsed.as_secs_f64(),
+        snapshot.population()
+    );
+    if !stability.is_settled() {
+        println!("The world has not settled into a cycle of up to {MAX_PERIOD} generations");
+    }
+
+    let path = options
+        .output
+        .clone()
+        .unwrap_or_else(|| format!("generation-{}.rle", snapshot.generation));
+    if let Err(e) = export(&snapshot, &options.automaton, &path) {
+        eprintln!("Cannot save {path}: {e}");
+        std::process::exit(1);
+    }
+    println!("Saved generation {} to {path}", snapshot.generation);
+
+    if let Some(path) = &options.population {
+        population.collect(&device);
+        if let Err(e) = population.finish() {
+            eprintln!("Cannot save {path}: {e}");
+            std::process::exit(1);
+        }
+        println!("Saved the population history to {path}");
+    }
+}
+
 fn main() {
     env_logger::init();
 
-    let event_loop = EventLoop::new().unwrap();
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    println!("Running rule {} on a {} grid", options.automaton, options.boundary);
+    if options.generator.is_random() {
+        println!("Starting from a {} soup with seed {}", options.generator, options.seed);
+    }
 
-    // When the current loop iteration finishes, immediately begin a new
-    // iteration regardless of whether or not new events are available to
-    // process. Preferred for applications that want to render as fast as
-    // possible, like games.
-    // event_loop.set_control_flow(ControlFlow::Poll);
+    if let Some(generations) = options.benchmark {
+        benchmark(&options, generations);
+        return;
+    }
+    if let Some(generations) = options.headless {
+        headless(&options, generations);
+        return;
+    }
+
+    let event_loop = EventLoop::new().unwrap();
 
     // When the current loop iteration finishes, suspend the thread until
     // another event arrives. Helps keeping CPU utilization low if nothing
@@ -481,6 +803,6 @@ fn main() {
     // the background.
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut app = App::new();
+    let mut app = App::new(options);
     event_loop.run_app(&mut app).unwrap();
 }
\ No newline at end of file
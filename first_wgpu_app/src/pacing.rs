@@ -0,0 +1,121 @@
+//! Keeping the simulation rate independent of the display rate.
+use std::{
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+/// Even when asked to run faster, never record more generations than this in one frame,
+/// so that a slow GPU cannot fall further and further behind.
+pub const MAX_GENERATIONS_PER_FRAME: u32 = 100_000;
+
+/// The simulation rates that can be asked for. Any slower looks paused, and
+/// any faster is capped by `MAX_GENERATIONS_PER_FRAME` anyway.
+pub const GENERATIONS_PER_SECOND: RangeInclusive<f64> = (1.0 / 64.0)..=1e7;
+
+/// The frame rates that can be asked for.
+pub const FRAMES_PER_SECOND: RangeInclusive<f64> = 1.0..=1000.0;
+
+/// Turns wall-clock time into a number of generations to simulate.
+///
+/// Generations that come due between two frames accumulate, so e.g. 5
+/// generations per second at 60 frames per second advances the simulation
+/// on every twelfth frame, and 6000 per second runs 100 generations per frame.
+#[derive(Debug)]
+pub struct SimulationClock {
+    generations_per_second: f64,
+    /// Generations that are due but have not been simulated yet, including a fractional part.
+    pending: f64,
+    last_tick: Option<Instant>,
+    paused: bool,
+}
+
+impl SimulationClock {
+    pub fn new(generations_per_second: f64) -> Self {
+        SimulationClock {
+            generations_per_second,
+            pending: 0.0,
+            last_tick: None,
+            paused: false,
+        }
+    }
+
+    pub fn generations_per_second(&self) -> f64 {
+        self.generations_per_second
+    }
+
+    /// Changes the rate without losing generations that are already due,
+    /// keeping it within `GENERATIONS_PER_SECOND`.
+    pub fn set_generations_per_second(&mut self, generations_per_second: f64) {
+        self.generations_per_second =
+            generations_per_second.clamp(*GENERATIONS_PER_SECOND.start(), *GENERATIONS_PER_SECOND.end());
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops or restarts the clock. No generations come due while paused,
+    /// and the time spent paused is not made up for afterwards.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pending = 0.0;
+    }
+
+    /// Returns how many generations came due since the last call.
+    pub fn tick(&mut self, now: Instant) -> u32 {
+        let elapsed = self
+            .last_tick
+            .map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last_tick = Some(now);
+        if self.paused {
+            return 0;
+        }
+
+        self.pending += elapsed.as_secs_f64() * self.generations_per_second;
+        let due = self.pending.floor().min(MAX_GENERATIONS_PER_FRAME as f64);
+        // Drop any backlog beyond what a single frame may simulate.
+        self.pending = (self.pending - due).min(1.0);
+        due as u32
+    }
+}
+
+/// Decides when the next frame is due, so that the event loop can sleep until then.
+#[derive(Debug)]
+pub struct FrameScheduler {
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+impl FrameScheduler {
+    pub fn new(frames_per_second: f64, now: Instant) -> Self {
+        FrameScheduler {
+            frame_duration: Duration::from_secs_f64(1.0 / frames_per_second),
+            next_frame: now,
+        }
+    }
+
+    /// The deadline of the next frame.
+    pub fn next_frame(&self) -> Instant {
+        self.next_frame
+    }
+
+    /// Whether the next frame is due at `now`.
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_frame
+    }
+
+    /// Moves the deadline on after a frame was drawn at `now`.
+    ///
+    /// Deadlines stay on a fixed grid of frame durations. If drawing took so
+    /// long that deadlines were missed, those frames are skipped rather than
+    /// drawn back to back to catch up.
+    pub fn frame_drawn(&mut self, now: Instant) {
+        self.next_frame += self.frame_duration;
+        if self.next_frame <= now {
+            let behind = now - self.next_frame;
+            let missed = (behind.as_secs_f64() / self.frame_duration.as_secs_f64()) as u32 + 1;
+            log::debug!("missed {missed} frame deadline(s)");
+            self.next_frame += self.frame_duration * missed;
+        }
+    }
+}
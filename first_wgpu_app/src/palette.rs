@@ -0,0 +1,336 @@
+//! The colors the window draws with, built in or loaded from palette files.
+//!
+//! Palette files are a small subset of TOML: one `key = value` per line,
+//! where a value is a color in double quotes or an array of them, and `#`
+//! starts a comment outside quotes. Colors are written `#rrggbb` or
+//! `#rrggbbaa` in sRGB, like in most color pickers. Every key is optional and
+//! defaults to the built-in palette:
+//!
+//! ```toml
+//! name = "embers"
+//! background = "#1a0a00"             # the clear color, outside the grid
+//! dead = "#00000000"                 # dead cells, transparent by default
+//! alive = ["#ffcc33", "#ff6600"]     # live cells by position, see below
+//! dying = ["#ff9933", "#330000"]     # decaying states of Generations rules
+//! states = ["#ff0000", "#0000ff"]    # the states of rule tables
+//! age = ["#ffffcc", "#ff3300"]       # the color modes, see `coloring.rs`
+//! heat = ["#000000", "#ffffff"]
+//! neighbors = ["#0000ff", "#ff0000"]
+//! ```
+//!
+//! `alive` takes one color, two for the left and right edges of the grid, or
+//! four for its bottom left, bottom right, top left and top right corners,
+//! blended in between. The others keys holding arrays are gradients of 1 to
+//! `MAX_STOPS` evenly spaced colors.
+use std::{fmt, fs, io, path::Path};
+
+use crate::coloring::ColorMode;
+
+/// The most colors in a gradient, as `MAX_STOPS` in `shader.wgsl`.
+pub const MAX_STOPS: usize = 8;
+
+/// A linear RGBA color, as the shaders use them.
+pub type Color = [f32; 4];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    /// The clear color, shown outside the grid and wherever no cell is drawn.
+    pub background: Color,
+    /// Dead cells, usually transparent so that the background shows.
+    pub dead: Color,
+    /// Live cells colored by position, at the bottom left, bottom right, top
+    /// left and top right corners of the grid.
+    pub alive: [Color; 4],
+    /// Decaying states of Generations rules, from the first to the last.
+    pub dying: Vec<Color>,
+    /// The non-dead states of rule tables, from the first to the last.
+    pub states: Vec<Color>,
+    /// From newborn to long-lived cells.
+    pub age: Vec<Color>,
+    /// From cold to cells that changed in the last generation.
+    pub heat: Vec<Color>,
+    /// From no active neighbors to eight.
+    pub neighbors: Vec<Color>,
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    /// A malformed line, with its 1-based line number.
+    Syntax { line: usize, message: String },
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(e) => write!(f, "cannot read palette: {e}"),
+            PaletteError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<io::Error> for PaletteError {
+    fn from(e: io::Error) -> Self {
+        PaletteError::Io(e)
+    }
+}
+
+/// A fully saturated color with hue `h` in [0, 1].
+fn hue(h: f32) -> Color {
+    let channel = |n: f32| {
+        let k = (n + h * 6.0) % 6.0;
+        1.0 - k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+    [channel(5.0), channel(3.0), channel(1.0), 1.0]
+}
+
+/// `MAX_STOPS` colors going around the hue circle from `from` to `to`.
+fn hues(from: f32, to: f32) -> Vec<Color> {
+    (0..MAX_STOPS)
+        .map(|i| hue(from + (to - from) * i as f32 / (MAX_STOPS - 1) as f32))
+        .collect()
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            name: "default".to_string(),
+            background: [0.0, 0.0, 0.3, 1.0],
+            dead: [0.0; 4],
+            alive: [[0.0, 0.0, 1.0, 1.0], [1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 1.0, 1.0], [1.0, 1.0, 0.0, 1.0]],
+            // Embers fading towards the background.
+            dying: vec![[1.0, 0.6, 0.1, 1.0], [0.3, 0.0, 0.2, 1.0]],
+            // Stopping short of a full turn, so that the last one does not
+            // wrap around to red.
+            states: hues(0.0, 0.75),
+            age: hues(0.15, 0.65),
+            // Black-body colors.
+            heat: vec![[0.0, 0.0, 0.0, 1.0], [1.0, 0.0, 0.0, 1.0], [1.0, 1.0, 0.0, 1.0], [1.0; 4]],
+            neighbors: hues(0.0, 0.75),
+        }
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`, in sRGB, into a linear color.
+fn parse_color(color: &str) -> Result<Color, String> {
+    let invalid = || format!("invalid color {color:?}, expected #rrggbb or #rrggbbaa");
+    let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut channels = [255; 4];
+    for (channel, i) in channels.iter_mut().zip((0..hex.len()).step_by(2)) {
+        *channel = u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid())?;
+    }
+    let linear = |c: u8| {
+        let c = f32::from(c) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let [r, g, b, a] = channels;
+    Ok([linear(r), linear(g), linear(b), f32::from(a) / 255.0])
+}
+
+/// Parses a value: a quoted string, or an array of them on the same line.
+pub fn parse_value(value: &str) -> Result<Vec<String>, String> {
+    let quoted = |item: &str| {
+        item.strip_prefix('"')
+            .and_then(|item| item.strip_suffix('"'))
+            .filter(|item| !item.contains('"'))
+            .map(str::to_string)
+            .ok_or_else(|| format!("expected a string in double quotes, found {item:?}"))
+    };
+    match value.strip_prefix('[') {
+        Some(items) => {
+            let items = items.strip_suffix(']').ok_or("expected ] at the end of the array")?;
+            items
+                .split(',')
+                .map(str::trim)
+                // A trailing comma is allowed.
+                .filter(|item| !item.is_empty())
+                .map(quoted)
+                .collect()
+        }
+        None => Ok(vec![quoted(value)?]),
+    }
+}
+
+/// Removes a comment from the end of `line`, leaving `#` in strings alone.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+impl Palette {
+    /// Loads a palette file, named after the file unless it has a `name`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PaletteError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let fallback = path.file_stem().and_then(|s| s.to_str()).unwrap_or("palette");
+        Self::parse(&text, fallback)
+    }
+
+    /// Parses the text of a palette file. `name` is used if it has no `name` key.
+    pub fn parse(text: &str, name: &str) -> Result<Self, PaletteError> {
+        let mut palette = Palette {
+            name: name.to_string(),
+            ..Palette::default()
+        };
+        for (index, line) in text.lines().enumerate() {
+            let syntax = |message: String| PaletteError::Syntax { line: index + 1, message };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| syntax(format!("expected key = value, found {line:?}")))?;
+            let (key, values) = (key.trim(), parse_value(value.trim()).map_err(syntax)?);
+            if key == "name" {
+                palette.name = match <[String; 1]>::try_from(values) {
+                    Ok([name]) => name,
+                    Err(_) => return Err(syntax("the name must be a single string".to_string())),
+                };
+                continue;
+            }
+
+            let colors = values
+                .iter()
+                .map(|color| parse_color(color))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(syntax)?;
+            let single = |colors: Vec<Color>| match colors[..] {
+                [color] => Ok(color),
+                _ => Err(syntax(format!("{key} must be a single color"))),
+            };
+            let gradient = |colors: Vec<Color>| match colors.len() {
+                1..=MAX_STOPS => Ok(colors),
+                _ => Err(syntax(format!("{key} must have 1 to {MAX_STOPS} colors"))),
+            };
+            match key {
+                "background" => palette.background = single(colors)?,
+                "dead" => palette.dead = single(colors)?,
+                "alive" => {
+                    palette.alive = match colors[..] {
+                        [color] => [color; 4],
+                        [left, right] => [left, right, left, right],
+                        [bottom_left, bottom_right, top_left, top_right] => {
+                            [bottom_left, bottom_right, top_left, top_right]
+                        }
+                        _ => return Err(syntax("alive must have 1, 2 or 4 colors".to_string())),
+                    }
+                }
+                "dying" => palette.dying = gradient(colors)?,
+                "states" => palette.states = gradient(colors)?,
+                "age" => palette.age = gradient(colors)?,
+                "heat" => palette.heat = gradient(colors)?,
+                "neighbors" => palette.neighbors = gradient(colors)?,
+                _ => return Err(syntax(format!("unknown key {key:?}"))),
+            }
+        }
+        Ok(palette)
+    }
+
+    /// The background as the clear color of a render pass.
+    pub fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b, a] = self.background.map(f64::from);
+        wgpu::Color { r, g, b, a }
+    }
+
+    /// The contents of the coloring uniform, laid out like `Coloring` in
+    /// `shader.wgsl`: the color mode, then the dead color, the four alive
+    /// colors, and the gradients, each its number of colors followed by
+    /// `MAX_STOPS` colors. Everything is aligned to 16 bytes.
+    pub fn uniform_data(&self, mode: ColorMode) -> Vec<u32> {
+        let mut data = vec![mode.shader_value(), 0, 0, 0];
+        let mut push = |colors: &[Color]| data.extend(colors.iter().flatten().map(|c| c.to_bits()));
+        push(&[self.dead]);
+        push(&self.alive);
+        for gradient in [&self.dying, &self.states, &self.age, &self.heat, &self.neighbors] {
+            data.extend([gradient.len() as u32, 0, 0, 0]);
+            let mut stops = [[0.0; 4]; MAX_STOPS];
+            stops[..gradient.len()].copy_from_slice(gradient);
+            data.extend(stops.iter().flatten().map(|c| c.to_bits()));
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_error_line(text: &str) -> usize {
+        match Palette::parse(text, "test") {
+            Err(PaletteError::Syntax { line, .. }) => line,
+            other => panic!("expected a syntax error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn colors_are_converted_from_srgb() {
+        assert_eq!(parse_color("#000000"), Ok([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#ffffff80"), Ok([1.0, 1.0, 1.0, 128.0 / 255.0]));
+        // Mid grey in sRGB is about a fifth of the light.
+        let [r, ..] = parse_color("#808080").unwrap();
+        assert!((r - 0.2158).abs() < 1e-4, "{r}");
+        for invalid in ["808080", "#80808", "#8080808080", "#gggggg", "#ééé"] {
+            assert!(parse_color(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn keys_left_out_keep_the_default() {
+        let text = "# A comment\n\nbackground = \"#ffffff\"  # white\nalive = [\"#000000\", \"#ff0000\",]\nage = [\"#000000\"]\n";
+        let palette = Palette::parse(text, "light").unwrap();
+        let (black, red) = ([0.0, 0.0, 0.0, 1.0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(palette.name, "light");
+        assert_eq!(palette.background, [1.0; 4]);
+        assert_eq!(palette.alive, [black, red, black, red]);
+        assert_eq!(palette.age, [black]);
+        assert_eq!(palette.heat, Palette::default().heat);
+        assert_eq!(Palette::parse("name = \"dark\"", "light").unwrap().name, "dark");
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        assert_eq!(syntax_error_line("background = \"#000000\"\nforeground = \"#000000\""), 2);
+        assert_eq!(syntax_error_line("\n\nbackground"), 3);
+        assert_eq!(syntax_error_line("background = [\"#000000\", \"#ffffff\"]"), 1);
+        assert_eq!(syntax_error_line("alive = [\"#000000\", \"#000000\", \"#000000\"]"), 1);
+        assert_eq!(syntax_error_line("heat = []"), 1);
+        assert_eq!(syntax_error_line("heat = [\"#000000\""), 1);
+        assert_eq!(syntax_error_line("dead = #000000"), 1);
+    }
+
+    #[test]
+    fn sample_palettes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("palettes");
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            Palette::load(&path).unwrap_or_else(|e| panic!("cannot load {}: {e}", path.display()));
+        }
+    }
+
+    #[test]
+    fn uniform_data_fills_every_gradient() {
+        let data = Palette::default().uniform_data(ColorMode::Heat);
+        assert_eq!(data.len(), 4 + 4 + 16 + 5 * (4 + 4 * MAX_STOPS));
+        assert_eq!(data[0], ColorMode::Heat.shader_value());
+        // The number of colors of the heat gradient, after three others.
+        assert_eq!(data[24 + 3 * (4 + 4 * MAX_STOPS)], 4);
+    }
+}
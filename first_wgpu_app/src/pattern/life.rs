@@ -0,0 +1,230 @@
+//! The Life 1.05 and Life 1.06 pattern formats (`.lif` or `.life`).
+//!
+//! Life 1.05 lists blocks of cells, each placed relative to the center by a
+//! `#P x y` line, drawn with `.` for dead and `*` for live cells:
+//!
+//! ```text
+//! #Life 1.05
+//! #D Glider
+//! #N
+//! #P -1 -1
+//! .*.
+//! ..*
+//! ***
+//! ```
+//!
+//! Life 1.06 lists the coordinates of the live cells, one `x y` per line:
+//!
+//! ```text
+//! #Life 1.06
+//! 0 -1
+//! 1 0
+//! -1 1
+//! 0 1
+//! 1 1
+//! ```
+//!
+//! Both store Life-like patterns only. `#N` in Life 1.05 selects Conway's
+//! rule, and `#R` another one written as `<survival>/<birth>`.
+use std::str::FromStr;
+
+use super::{Format, Pattern, PatternError};
+use crate::rule::Rule;
+
+const HEADER_105: &str = "#Life 1.05";
+const HEADER_106: &str = "#Life 1.06";
+
+/// Tells Life 1.05 and Life 1.06 apart by their header.
+pub fn version(text: &str) -> Option<Format> {
+    let header = text.lines().next().unwrap_or("").trim();
+    if header.eq_ignore_ascii_case(HEADER_105) {
+        Some(Format::Life105)
+    } else if header.eq_ignore_ascii_case(HEADER_106) {
+        Some(Format::Life106)
+    } else {
+        None
+    }
+}
+
+fn check_header(text: &str, header: &str) -> Result<(), PatternError> {
+    let found = text.lines().next().unwrap_or("").trim();
+    if !found.eq_ignore_ascii_case(header) {
+        return Err(PatternError::Syntax {
+            line: 1,
+            message: format!("expected the {header:?} header, found {found:?}"),
+        });
+    }
+    Ok(())
+}
+
+/// Parses a signed coordinate.
+fn coordinate(value: Option<&str>, line: usize) -> Result<i64, PatternError> {
+    let value = value.ok_or_else(|| PatternError::Syntax {
+        line,
+        message: "expected an x and a y coordinate".to_string(),
+    })?;
+    value.parse().map_err(|_| PatternError::Syntax {
+        line,
+        message: format!("invalid coordinate {value:?}"),
+    })
+}
+
+/// The error for a cell placed past the largest coordinate.
+fn out_of_range(line: usize) -> PatternError {
+    PatternError::Syntax {
+        line,
+        message: format!("cell coordinates out of range, the largest is {}", i64::MAX),
+    }
+}
+
+/// Reads a Life 1.05 pattern.
+pub fn parse_105(text: &str) -> Result<Pattern, PatternError> {
+    check_header(text, HEADER_105)?;
+    let mut cells = Vec::new();
+    let (mut comments, mut rule) = (Vec::new(), None);
+    // Where the next row of the current block goes.
+    let (mut left, mut y) = (0, 0);
+
+    for (index, raw) in text.lines().enumerate().skip(1) {
+        let line_no = index + 1;
+        let line = raw.trim();
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut chars = directive.chars();
+            let kind = chars.next();
+            let rest = chars.as_str().trim();
+            match kind {
+                Some('D' | 'C') => comments.push(rest.to_string()),
+                Some('N') => rule = Some(Rule::CONWAY.to_string()),
+                Some('R') => rule = Some(rest.to_string()),
+                Some('P') => {
+                    let mut parts = rest.split_whitespace();
+                    left = coordinate(parts.next(), line_no)?;
+                    y = coordinate(parts.next(), line_no)?;
+                }
+                _ => (),
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '.' => (),
+                '*' => cells.push((left.checked_add(x as i64).ok_or_else(|| out_of_range(line_no))?, y, 1)),
+                _ => {
+                    return Err(PatternError::Syntax {
+                        line: line_no,
+                        message: format!("unexpected character {c:?}, expected '.' or '*'"),
+                    })
+                }
+            }
+        }
+        y = y.checked_add(1).ok_or_else(|| out_of_range(line_no))?;
+    }
+
+    Ok(Pattern {
+        comments,
+        rule,
+        ..Pattern::from_coordinates(cells)?
+    })
+}
+
+/// Reads a Life 1.06 pattern.
+pub fn parse_106(text: &str) -> Result<Pattern, PatternError> {
+    check_header(text, HEADER_106)?;
+    let mut cells = Vec::new();
+    let mut comments = Vec::new();
+
+    for (index, raw) in text.lines().enumerate().skip(1) {
+        let line_no = index + 1;
+        let line = raw.trim();
+        // Comments are not part of Life 1.06, but some programs write them anyway.
+        if let Some(comment) = line.strip_prefix('#') {
+            comments.push(comment.trim_start_matches(['D', 'C']).trim().to_string());
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let x = coordinate(parts.next(), line_no)?;
+        let y = coordinate(parts.next(), line_no)?;
+        if parts.next().is_some() {
+            return Err(PatternError::Syntax {
+                line: line_no,
+                message: "expected only an x and a y coordinate".to_string(),
+            });
+        }
+        cells.push((x, y, 1));
+    }
+
+    Ok(Pattern {
+        comments,
+        ..Pattern::from_coordinates(cells)?
+    })
+}
+
+fn two_states_only(pattern: &Pattern) -> Result<(), PatternError> {
+    if pattern.max_state() > 1 {
+        return Err(PatternError::Unsupported(
+            "Life 1.05 and 1.06 patterns can only store two states".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Writes a pattern as Life 1.05, in a single block centered on the origin.
+pub fn write_105(pattern: &Pattern) -> Result<String, PatternError> {
+    two_states_only(pattern)?;
+
+    let mut out = format!("{HEADER_105}\n");
+    if let Some(name) = &pattern.name {
+        out += &format!("#D {name}\n");
+    }
+    for comment in &pattern.comments {
+        out += &format!("#D {comment}\n");
+    }
+    // Life 1.05 can only name Life-like rules, other rules are left out.
+    match pattern.rule.as_deref().map(Rule::from_str) {
+        Some(Ok(rule)) if rule == Rule::CONWAY => out += "#N\n",
+        Some(Ok(rule)) if rule.states == 2 => {
+            let counts = |mask: u32| -> String {
+                (0..=8).filter(|n| mask & (1 << n) != 0).map(|n| n.to_string()).collect()
+            };
+            out += &format!("#R {}/{}\n", counts(rule.survival), counts(rule.birth));
+        }
+        _ => (),
+    }
+
+    let (left, top) = (-i64::from(pattern.width / 2), -i64::from(pattern.height / 2));
+    out += &format!("#P {left} {top}\n");
+    let mut rows = vec![vec!['.'; pattern.width as usize]; pattern.height as usize];
+    for &(x, y, _) in &pattern.cells {
+        rows[y as usize][x as usize] = '*';
+    }
+    for row in rows {
+        // Trailing dead cells are implied, but an empty line would not count
+        // as a row, so empty rows keep a single dead cell.
+        let row: String = row.into_iter().collect();
+        match row.trim_end_matches('.') {
+            "" => out.push('.'),
+            live => out += live,
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Writes a pattern as Life 1.06, with its top left corner at the origin.
+pub fn write_106(pattern: &Pattern) -> Result<String, PatternError> {
+    two_states_only(pattern)?;
+
+    let mut out = format!("{HEADER_106}\n");
+    for &(x, y, _) in &pattern.cells {
+        out += &format!("{x} {y}\n");
+    }
+    Ok(out)
+}
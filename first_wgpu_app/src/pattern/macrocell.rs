@@ -0,0 +1,336 @@
+//! Golly's Macrocell (`.mc`) pattern format.
+//!
+//! ```text
+//! [M2] (golly 4.2)
+//! #R B3/S23
+//! .*$..*$***$
+//! 4 1 0 0 0
+//! ```
+//!
+//! A Macrocell file stores the pattern as a quadtree with shared subtrees,
+//! one node per line after the header and `#` lines. Nodes are numbered from
+//! 1 in the order they appear, and the last one is the root. A node of level
+//! `k` covers a square of `2^k` cells and is written as `k nw ne sw se`,
+//! naming its four quadrants by number, where 0 is an empty quadrant.
+//!
+//! Two-state patterns store the 8x8 squares of level 3 directly, as rows of
+//! `.` and `*` each ended by `$`. Patterns with more states instead write
+//! level 1 nodes whose quadrants are the states of single cells.
+use std::collections::HashMap;
+
+use super::{Pattern, PatternError};
+use crate::cli::MAX_GRID_SIZE;
+
+const HEADER: &str = "[M2]";
+
+/// The deepest tree accepted, so that cell coordinates fit in an `i64`.
+const MAX_LEVEL: u32 = 62;
+
+#[derive(Debug)]
+enum Node {
+    /// A level 3 square of a two-state pattern, as its live `(column, row)` cells.
+    Leaf(Vec<(u32, u32)>),
+    /// A level 1 square of a multi-state pattern, as the states of its cells.
+    Cells([u32; 4]),
+    /// A larger square, split into quadrants.
+    Quadrants { level: u32, children: [usize; 4] },
+}
+
+impl Node {
+    fn level(&self) -> u32 {
+        match self {
+            Node::Leaf(_) => 3,
+            Node::Cells(_) => 1,
+            Node::Quadrants { level, .. } => *level,
+        }
+    }
+}
+
+fn parse_leaf(line: &str, line_no: usize) -> Result<Node, PatternError> {
+    let mut cells = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    for c in line.chars() {
+        match c {
+            '.' => x += 1,
+            '*' => {
+                cells.push((x, y));
+                x += 1;
+            }
+            '$' => (x, y) = (0, y + 1),
+            _ => {
+                return Err(PatternError::Syntax {
+                    line: line_no,
+                    message: format!("unexpected character {c:?} in an 8x8 square"),
+                })
+            }
+        }
+        if x > 8 || (y >= 8 && c != '$') || y > 8 {
+            return Err(PatternError::Syntax {
+                line: line_no,
+                message: "an 8x8 square has more than 8 rows or columns".to_string(),
+            });
+        }
+    }
+    Ok(Node::Leaf(cells))
+}
+
+fn parse_node(line: &str, line_no: usize, nodes: &[Node]) -> Result<Node, PatternError> {
+    let syntax = |message: String| PatternError::Syntax { line: line_no, message };
+    let numbers = line
+        .split_whitespace()
+        .map(|part| part.parse::<usize>().map_err(|_| syntax(format!("invalid number {part:?}"))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let [level, nw, ne, sw, se] = numbers[..] else {
+        return Err(syntax(format!("expected a level and four quadrants, found {line:?}")));
+    };
+    let quadrants = [nw, ne, sw, se];
+
+    match level {
+        1 => {
+            let states = quadrants.map(|state| state as u32);
+            if let Some(state) = states.iter().find(|&&state| state >= crate::rule::MAX_STATES) {
+                return Err(syntax(format!("state {state} is too large")));
+            }
+            Ok(Node::Cells(states))
+        }
+        _ if (2..=MAX_LEVEL as usize).contains(&level) => {
+            let level = level as u32;
+            for child in quadrants.into_iter().filter(|&child| child != 0) {
+                let Some(node) = nodes.get(child - 1) else {
+                    return Err(syntax(format!("node {child} is not defined before it is used")));
+                };
+                if node.level() != level - 1 {
+                    return Err(syntax(format!(
+                        "a level {level} node needs level {} quadrants, but node {child} has level {}",
+                        level - 1,
+                        node.level()
+                    )));
+                }
+            }
+            Ok(Node::Quadrants { level, children: quadrants })
+        }
+        _ => Err(syntax(format!("invalid level {level}, expected 1 to {MAX_LEVEL}"))),
+    }
+}
+
+/// The box around the non-dead cells of a node as `(left, top, right, bottom)`,
+/// measured from its top left corner, or `None` if the node is empty.
+type Bounds = Option<(u64, u64, u64, u64)>;
+
+/// Finds the bounds of `node`, given those of the nodes before it.
+fn bounds(node: &Node, known: &[Bounds]) -> Bounds {
+    let corners: Vec<(u64, u64)> = match node {
+        Node::Leaf(live) => live.iter().map(|&(x, y)| (u64::from(x), u64::from(y))).collect(),
+        Node::Cells(states) => (0..4)
+            .filter(|&i| states[i] != 0)
+            .map(|i| ((i % 2) as u64, (i / 2) as u64))
+            .collect(),
+        Node::Quadrants { level, children } => {
+            let half = 1u64 << (level - 1);
+            let child_bounds = children.iter().map(|&child| if child == 0 { None } else { known[child - 1] });
+            child_bounds
+                .enumerate()
+                .filter_map(|(i, bounds)| bounds.map(|bounds| (i, bounds)))
+                .flat_map(|(i, (left, top, right, bottom))| {
+                    let (dx, dy) = ((i % 2) as u64 * half, (i / 2) as u64 * half);
+                    [(left + dx, top + dy), (right + dx, bottom + dy)]
+                })
+                .collect()
+        }
+    };
+    let left = corners.iter().map(|c| c.0).min()?;
+    let top = corners.iter().map(|c| c.1).min()?;
+    let right = corners.iter().map(|c| c.0).max()?;
+    let bottom = corners.iter().map(|c| c.1).max()?;
+    Some((left, top, right, bottom))
+}
+
+/// Lists the non-dead cells of node `id`, whose top left corner is at `(x, y)`.
+/// Empty nodes are skipped, however deep the tree below them.
+fn expand(nodes: &[Node], bounds: &[Bounds], id: usize, x: i64, y: i64, cells: &mut Vec<(i64, i64, u32)>) {
+    if id == 0 || bounds[id - 1].is_none() {
+        return;
+    }
+    match &nodes[id - 1] {
+        Node::Leaf(live) => cells.extend(live.iter().map(|&(dx, dy)| (x + dx as i64, y + dy as i64, 1))),
+        Node::Cells(states) => {
+            for (i, &state) in states.iter().enumerate() {
+                cells.push((x + (i % 2) as i64, y + (i / 2) as i64, state));
+            }
+        }
+        Node::Quadrants { level, children } => {
+            let half = 1i64 << (level - 1);
+            for (i, &child) in children.iter().enumerate() {
+                expand(nodes, bounds, child, x + (i % 2) as i64 * half, y + (i / 2) as i64 * half, cells);
+            }
+        }
+    }
+}
+
+/// Reads a Macrocell pattern.
+pub fn parse(text: &str) -> Result<Pattern, PatternError> {
+    let mut lines = text.lines().enumerate();
+    let header = lines.next().map_or("", |(_, line)| line.trim());
+    if !header.starts_with(HEADER) {
+        return Err(PatternError::Syntax {
+            line: 1,
+            message: format!("expected the {HEADER:?} header, found {header:?}"),
+        });
+    }
+
+    let (mut name, mut comments, mut rule) = (None, Vec::new(), None);
+    let (mut nodes, mut bounds_of) = (Vec::new(), Vec::new());
+    let mut root_line = 1;
+    for (index, raw) in lines {
+        let line_no = index + 1;
+        let line = raw.trim();
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut chars = directive.chars();
+            let kind = chars.next();
+            let rest = chars.as_str().trim().to_string();
+            match kind {
+                Some('R') => rule = Some(rest),
+                Some('N') => name = Some(rest),
+                Some('C' | 'D') => comments.push(rest),
+                // `#G` gives the generation, which a pattern does not keep.
+                _ => (),
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let node = if line.starts_with(['.', '*', '$']) {
+            parse_leaf(line, line_no)?
+        } else {
+            parse_node(line, line_no, &nodes)?
+        };
+        bounds_of.push(bounds(&node, &bounds_of));
+        nodes.push(node);
+        root_line = line_no;
+    }
+
+    // Shared subtrees let a short file describe far more cells than any grid
+    // holds, so the size is checked before the tree is expanded.
+    if let Some(&Some((left, top, right, bottom))) = bounds_of.last() {
+        let extent = (right - left).max(bottom - top);
+        if extent >= u64::from(MAX_GRID_SIZE) {
+            return Err(PatternError::Syntax {
+                line: root_line,
+                message: format!(
+                    "the pattern is {} cells across, larger than the largest grid of {MAX_GRID_SIZE}",
+                    u128::from(extent) + 1
+                ),
+            });
+        }
+    }
+
+    let mut cells = Vec::new();
+    expand(&nodes, &bounds_of, nodes.len(), 0, 0, &mut cells);
+    Ok(Pattern {
+        name,
+        comments,
+        rule,
+        ..Pattern::from_coordinates(cells)?
+    })
+}
+
+/// Builds the quadtree of a pattern, sharing identical subtrees.
+struct Writer {
+    multi_state: bool,
+    /// The node lines in order, each node after its quadrants.
+    lines: Vec<String>,
+    /// The number of every node line written so far.
+    numbers: HashMap<String, usize>,
+}
+
+impl Writer {
+    /// Adds a node line, or finds the identical one written before, and returns its number.
+    fn add(&mut self, line: String) -> usize {
+        if let Some(&number) = self.numbers.get(&line) {
+            return number;
+        }
+        self.lines.push(line.clone());
+        self.numbers.insert(line, self.lines.len());
+        self.lines.len()
+    }
+
+    /// Writes the node of `level` with its top left corner at `(x, y)`, given
+    /// the cells inside it, and returns its number, or 0 if it is empty.
+    fn node(&mut self, level: u32, x: u32, y: u32, cells: &[(u32, u32, u32)]) -> usize {
+        if cells.is_empty() {
+            return 0;
+        }
+        if level == 3 && !self.multi_state {
+            let mut rows = vec![String::new(); 8];
+            for &(cx, cy, _) in cells {
+                let row = &mut rows[(cy - y) as usize];
+                let column = (cx - x) as usize;
+                row.extend(std::iter::repeat_n('.', column - row.len()));
+                row.push('*');
+            }
+            while rows.last().is_some_and(String::is_empty) {
+                rows.pop();
+            }
+            return self.add(rows.into_iter().map(|row| row + "$").collect());
+        }
+        if level == 1 {
+            let mut states = [0; 4];
+            for &(cx, cy, state) in cells {
+                states[((cy - y) * 2 + cx - x) as usize] = state;
+            }
+            let [nw, ne, sw, se] = states;
+            return self.add(format!("1 {nw} {ne} {sw} {se}"));
+        }
+
+        let half = 1 << (level - 1);
+        let mut quadrants: [Vec<_>; 4] = Default::default();
+        for &cell in cells {
+            let right = u32::from(cell.0 - x >= half);
+            let bottom = u32::from(cell.1 - y >= half);
+            quadrants[(bottom * 2 + right) as usize].push(cell);
+        }
+        let [nw, ne, sw, se] = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .map(|(i, j)| self.node(level - 1, x + i * half, y + j * half, &quadrants[(j * 2 + i) as usize]));
+        self.add(format!("{level} {nw} {ne} {sw} {se}"))
+    }
+}
+
+/// Writes a pattern as Macrocell, using level 1 nodes if any cell has a state above 1.
+pub fn write(pattern: &Pattern) -> String {
+    let multi_state = pattern.max_state() > 1;
+    let mut out = format!("{HEADER} (first_wgpu_app)\n");
+    if let Some(rule) = &pattern.rule {
+        out += &format!("#R {rule}\n");
+    }
+    if let Some(name) = &pattern.name {
+        out += &format!("#N {name}\n");
+    }
+    for comment in &pattern.comments {
+        out += &format!("#C {comment}\n");
+    }
+
+    // The smallest tree covering the pattern.
+    let mut level = if multi_state { 1 } else { 3 };
+    while (1u64 << level) < u64::from(pattern.width.max(pattern.height)) {
+        level += 1;
+    }
+
+    // Squares are filled in reading order.
+    let mut cells = pattern.cells.clone();
+    cells.sort_by_key(|&(x, y, _)| (y, x));
+    let mut writer = Writer {
+        multi_state,
+        lines: Vec::new(),
+        numbers: HashMap::new(),
+    };
+    if writer.node(level, 0, 0, &cells) == 0 {
+        // Even an empty pattern needs a root.
+        writer.lines.push(if multi_state { "1 0 0 0 0" } else { "$" }.to_string());
+    }
+    for line in writer.lines {
+        out += &line;
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,431 @@
+//! Patterns loaded from and saved to pattern files.
+//!
+//! Every format is read into the same sparse [`Pattern`], which lists the
+//! non-dead cells of a rectangle. Like in the files themselves, rows are
+//! counted from the top.
+pub mod life;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+
+use std::{fmt, fs, io, path::Path};
+
+use crate::cli::MAX_GRID_SIZE;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pattern {
+    pub width: u32,
+    pub height: u32,
+    /// The non-dead cells as `(column, row, state)`, in reading order.
+    pub cells: Vec<(u32, u32, u32)>,
+    /// The rule the pattern was made for, as written in the file.
+    pub rule: Option<String>,
+    pub name: Option<String>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum PatternError {
+    Io(io::Error),
+    /// A malformed line, with its 1-based line number.
+    Syntax { line: usize, message: String },
+    /// The file extension does not belong to a known format.
+    UnknownFormat(String),
+    /// The pattern cannot be written in the chosen format, e.g. because it
+    /// has more states than the format can store.
+    Unsupported(String),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::Io(e) => write!(f, "cannot read pattern: {e}"),
+            PatternError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            PatternError::UnknownFormat(ext) => write!(f, "unknown pattern format {ext:?}"),
+            PatternError::Unsupported(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl From<io::Error> for PatternError {
+    fn from(e: io::Error) -> Self {
+        PatternError::Io(e)
+    }
+}
+
+/// The pattern file formats, named after their usual extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Run Length Encoded, `.rle`.
+    Rle,
+    /// Plaintext, `.cells`.
+    Plaintext,
+    /// Life 1.05, `.lif` or `.life` files starting with `#Life 1.05`.
+    Life105,
+    /// Life 1.06, `.lif` or `.life` files starting with `#Life 1.06`.
+    Life106,
+    /// Golly's Macrocell, `.mc`.
+    Macrocell,
+}
+
+impl Format {
+    /// The format for a file extension. Life 1.05 and 1.06 share their
+    /// extensions, so `.lif` is taken to mean the more common 1.06.
+    /// [`Format::detect`] then looks at the header.
+    pub fn from_extension(extension: &str) -> Result<Self, PatternError> {
+        match extension.to_ascii_lowercase().as_str() {
+            "rle" => Ok(Format::Rle),
+            "cells" => Ok(Format::Plaintext),
+            "lif" | "life" => Ok(Format::Life106),
+            "mc" => Ok(Format::Macrocell),
+            _ => Err(PatternError::UnknownFormat(extension.to_string())),
+        }
+    }
+
+    /// Refines the format guessed from an extension by the file contents `text`.
+    pub fn detect(self, text: &str) -> Self {
+        match self {
+            Format::Life105 | Format::Life106 => life::version(text).unwrap_or(self),
+            format => format,
+        }
+    }
+
+    /// Reads a pattern in this format.
+    pub fn parse(self, text: &str) -> Result<Pattern, PatternError> {
+        match self {
+            Format::Rle => rle::parse(text),
+            Format::Plaintext => plaintext::parse(text),
+            Format::Life105 => life::parse_105(text),
+            Format::Life106 => life::parse_106(text),
+            Format::Macrocell => macrocell::parse(text),
+        }
+    }
+
+    /// Writes a pattern in this format.
+    pub fn write(self, pattern: &Pattern) -> Result<String, PatternError> {
+        match self {
+            Format::Rle => Ok(rle::write(pattern)),
+            Format::Plaintext => plaintext::write(pattern),
+            Format::Life105 => life::write_105(pattern),
+            Format::Life106 => life::write_106(pattern),
+            Format::Macrocell => Ok(macrocell::write(pattern)),
+        }
+    }
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+impl Pattern {
+    /// Reads a pattern file, choosing the format by its extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PatternError> {
+        let path = path.as_ref();
+        let format = Format::from_extension(extension(path))?;
+        let text = fs::read_to_string(path)?;
+        format.detect(&text).parse(&text)
+    }
+
+    /// Writes a pattern file, choosing the format by its extension.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PatternError> {
+        let path = path.as_ref();
+        let text = Format::from_extension(extension(path))?.write(self)?;
+        Ok(fs::write(path, text)?)
+    }
+
+    /// Builds a pattern from cells at arbitrary, possibly negative coordinates,
+    /// as used by the formats that are not laid out on a rectangle. The
+    /// pattern is the bounding box of the cells. A cell listed twice keeps
+    /// its last state.
+    fn from_coordinates(cells: impl IntoIterator<Item = (i64, i64, u32)>) -> Result<Self, PatternError> {
+        let mut cells: Vec<_> = cells.into_iter().collect();
+        // A stable sort keeps duplicates in file order, so that the last one can be kept.
+        cells.sort_by_key(|&(x, y, _)| (y, x));
+        cells.reverse();
+        cells.dedup_by_key(|&mut (x, y, _)| (x, y));
+        cells.reverse();
+        cells.retain(|&(_, _, state)| state != 0);
+
+        let (Some(left), Some(right)) = (cells.iter().map(|c| c.0).min(), cells.iter().map(|c| c.0).max())
+        else {
+            return Ok(Pattern::default());
+        };
+        let (top, bottom) = (cells[0].1, cells[cells.len() - 1].1);
+        // `abs_diff` cannot overflow, even for cells at opposite ends of the i64 range.
+        let size = |low: i64, high: i64| match high.abs_diff(low) {
+            extent if extent < u64::from(MAX_GRID_SIZE) => Ok(extent as u32 + 1),
+            extent => Err(PatternError::Unsupported(format!(
+                "the pattern is too large, {extent} cells across, the largest grid is {MAX_GRID_SIZE}"
+            ))),
+        };
+
+        Ok(Pattern {
+            width: size(left, right)?,
+            height: size(top, bottom)?,
+            cells: cells
+                .into_iter()
+                .map(|(x, y, state)| (x.abs_diff(left) as u32, y.abs_diff(top) as u32, state))
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Builds a pattern from a dense grid of `width` by `height` cells, rows from
+    /// the top, cropped to the smallest rectangle holding every non-dead cell.
+    pub fn from_grid(cells: &[u32], width: u32, height: u32) -> Self {
+        let live = || {
+            cells
+                .iter()
+                .enumerate()
+                .filter(|(_, &state)| state != 0)
+                .map(|(i, &state)| (i as u32 % width, i as u32 / width, state))
+        };
+        let (Some(left), Some(right)) = (live().map(|c| c.0).min(), live().map(|c| c.0).max())
+        else {
+            return Pattern::default();
+        };
+        let top = live().map(|c| c.1).min().unwrap();
+        let bottom = live().map(|c| c.1).max().unwrap();
+        debug_assert!(bottom < height);
+
+        Pattern {
+            width: right - left + 1,
+            height: bottom - top + 1,
+            cells: live().map(|(x, y, state)| (x - left, y - top, state)).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The largest state of any cell, 0 for an empty pattern.
+    pub fn max_state(&self) -> u32 {
+        self.cells.iter().map(|c| c.2).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [Format; 5] = [
+        Format::Rle,
+        Format::Plaintext,
+        Format::Life105,
+        Format::Life106,
+        Format::Macrocell,
+    ];
+
+    fn sample(name: &str) -> Pattern {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("patterns").join(name);
+        Pattern::load(&path).unwrap_or_else(|e| panic!("cannot load {name}: {e}"))
+    }
+
+    fn samples() -> Vec<(String, Pattern)> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("patterns");
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names.into_iter().map(|name| (name.clone(), sample(&name))).collect()
+    }
+
+    fn syntax_error_line(format: Format, text: &str) -> usize {
+        match format.parse(text) {
+            Err(PatternError::Syntax { line, .. }) => line,
+            other => panic!("expected a syntax error from {format:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn glider_is_the_same_in_every_format() {
+        let glider = [(1, 0, 1), (2, 1, 1), (0, 2, 1), (1, 2, 1), (2, 2, 1)];
+        for name in ["glider.rle", "glider.cells", "glider.lif", "glider.mc"] {
+            let pattern = sample(name);
+            assert_eq!((pattern.width, pattern.height), (3, 3), "{name}");
+            assert_eq!(pattern.cells, glider, "{name}");
+        }
+    }
+
+    #[test]
+    fn samples_in_several_formats_agree() {
+        let pairs = [
+            ("gosper_glider_gun.rle", "gosper_glider_gun.cells"),
+            ("gosper_glider_gun.rle", "gosper_glider_gun.mc"),
+            ("wireworld_clock.rle", "wireworld_clock.mc"),
+        ];
+        for (a, b) in pairs {
+            let (a, b) = (sample(a), sample(b));
+            assert_eq!((a.width, a.height, &a.cells), (b.width, b.height, &b.cells));
+        }
+        let pulsar = sample("pulsar.lif");
+        assert_eq!((pulsar.width, pulsar.height, pulsar.cells.len()), (13, 13, 48));
+        assert_eq!(pulsar.rule.as_deref(), Some("B3/S23"));
+    }
+
+    #[test]
+    fn samples_survive_a_round_trip_through_every_format() {
+        for (name, pattern) in samples() {
+            for format in FORMATS {
+                let text = match format.write(&pattern) {
+                    Ok(text) => text,
+                    // Only the multi-state samples may be refused, by the two-state formats.
+                    Err(PatternError::Unsupported(_)) if pattern.max_state() > 1 => continue,
+                    Err(e) => panic!("cannot write {name} as {format:?}: {e}"),
+                };
+                let parsed = format
+                    .parse(&text)
+                    .unwrap_or_else(|e| panic!("cannot read back {name} as {format:?}: {e}\n{text}"));
+                assert_eq!(parsed.width, pattern.width, "{name} as {format:?}");
+                assert_eq!(parsed.height, pattern.height, "{name} as {format:?}");
+                assert_eq!(parsed.cells, pattern.cells, "{name} as {format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn metadata_survives_a_round_trip() {
+        let gun = sample("gosper_glider_gun.rle");
+        for format in [Format::Rle, Format::Macrocell] {
+            let parsed = format.parse(&format.write(&gun).unwrap()).unwrap();
+            assert_eq!(parsed.name.as_deref(), Some("Gosper glider gun"), "{format:?}");
+            assert_eq!(parsed.comments, gun.comments, "{format:?}");
+            assert_eq!(parsed.rule.as_deref(), Some("B3/S23"), "{format:?}");
+        }
+        let parsed = Format::Plaintext.parse(&Format::Plaintext.write(&gun).unwrap()).unwrap();
+        assert_eq!((parsed.name, parsed.comments), (gun.name, gun.comments));
+
+        let highlife = Pattern {
+            rule: Some("B36/S23".to_string()),
+            ..sample("glider.rle")
+        };
+        let text = Format::Life105.write(&highlife).unwrap();
+        assert!(text.contains("#R 23/36\n"), "{text}");
+        let parsed = Format::Life105.parse(&text).unwrap();
+        assert_eq!(parsed.rule.unwrap().parse::<crate::rule::Rule>(), "B36/S23".parse());
+    }
+
+    #[test]
+    fn rle_reads_multi_state_and_long_runs() {
+        let pattern = rle::parse("x = 30, y = 2, rule = B2/S/C3\n2pA$27.B!").unwrap();
+        assert_eq!(pattern.cells, [(0, 0, 25), (1, 0, 25), (27, 1, 2)]);
+        assert_eq!(pattern.max_state(), 25);
+        let round_trip = rle::parse(&rle::write(&pattern)).unwrap();
+        assert_eq!(round_trip.cells, pattern.cells);
+    }
+
+    #[test]
+    fn rle_reads_headers_comments_and_ragged_rows() {
+        let text = "#N Blinker and block\n#C Two still\n#c lifes.\n#r ignored\n\nx = 2, y = 4, rule = B3/S23\n3o$\n\n2$ 2o $2o!\nafter the end";
+        let pattern = rle::parse(text).unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Blinker and block"));
+        assert_eq!(pattern.comments, ["Two still", "lifes."]);
+        assert_eq!(pattern.rule.as_deref(), Some("B3/S23"));
+        // Rows may end early, and cells beyond the header grow the pattern.
+        assert_eq!((pattern.width, pattern.height), (3, 5));
+        assert_eq!(pattern.cells, [(0, 0, 1), (1, 0, 1), (2, 0, 1), (0, 3, 1), (1, 3, 1), (0, 4, 1), (1, 4, 1)]);
+        // A pattern without a rule or any cells.
+        let empty = rle::parse("x = 0, y = 0\n!").unwrap();
+        assert_eq!((empty.width, empty.height, empty.rule), (0, 0, None));
+    }
+
+    #[test]
+    fn rle_rejects_patterns_larger_than_the_largest_grid() {
+        // Runs that would overflow, or merely describe more cells than any grid holds.
+        assert_eq!(syntax_error_line(Format::Rle, "x = 1, y = 1\n4294967290b10o!"), 2);
+        assert_eq!(syntax_error_line(Format::Rle, "x = 1, y = 1\n4294967295$4294967295$o!"), 2);
+        assert_eq!(syntax_error_line(Format::Rle, "x = 1, y = 1\n4000000000o!"), 2);
+        assert_eq!(syntax_error_line(Format::Rle, "x = 1, y = 1\n99999999999o!"), 2);
+        assert_eq!(syntax_error_line(Format::Rle, &format!("x = 1, y = 1\n{MAX_GRID_SIZE}$o!")), 2);
+        assert_eq!(syntax_error_line(Format::Rle, &format!("x = {}, y = 1\no!", MAX_GRID_SIZE + 1)), 1);
+        // The largest grid itself is fine.
+        let edge = rle::parse(&format!("x = 1, y = 1\n{}b{}$o!", MAX_GRID_SIZE - 1, MAX_GRID_SIZE - 1)).unwrap();
+        assert_eq!(edge.cells, [(0, MAX_GRID_SIZE - 1, 1)]);
+        assert_eq!((edge.width, edge.height), (1, MAX_GRID_SIZE));
+    }
+
+    #[test]
+    fn extreme_coordinates_are_errors_not_overflows() {
+        assert_eq!(syntax_error_line(Format::Life105, "#Life 1.05\n#P 9223372036854775807 0\n.*"), 3);
+        assert_eq!(syntax_error_line(Format::Life105, "#Life 1.05\n#P 0 9223372036854775807\n*\n*"), 3);
+        for text in [
+            "#Life 1.06\n-9223372036854775808 0\n9223372036854775807 0",
+            "#Life 1.06\n0 -9223372036854775808\n0 9223372036854775807",
+            "#Life 1.06\n0 0\n65535 0",
+        ] {
+            assert!(matches!(Format::Life106.parse(text), Err(PatternError::Unsupported(_))), "{text:?}");
+        }
+        // Far from the origin is fine, as long as the pattern fits on a grid.
+        let far = Format::Life106.parse("#Life 1.06\n9223372036854775807 0\n9223372036854710273 1").unwrap();
+        assert_eq!((far.width, far.height), (MAX_GRID_SIZE, 2));
+    }
+
+    #[test]
+    fn macrocell_trees_larger_than_the_largest_grid_are_rejected() {
+        // Every level doubles the shared square, so ten lines describe 2^124 live cells.
+        let mut huge = "[M2]\n********$********$********$********$********$********$********$********$\n".to_string();
+        for level in 4..=62 {
+            let child = level - 3;
+            huge += &format!("{level} {child} {child} {child} {child}\n");
+        }
+        assert_eq!(syntax_error_line(Format::Macrocell, &huge), 61);
+        // Cells in opposite corners of a deep tree are as far apart as the tree is wide.
+        let mut sparse = "[M2]\n*$\n".to_string();
+        for level in 4..=19 {
+            sparse += &format!("{level} {} 0 0 0\n", level - 3);
+        }
+        sparse += "20 17 0 0 17\n";
+        assert_eq!(syntax_error_line(Format::Macrocell, &sparse), 19);
+        // Empty subtrees are skipped, however large the tree.
+        let mut deep = "[M2]\n.*$\n".to_string();
+        for level in 4..=62 {
+            deep += &format!("{level} {} 0 0 0\n", level - 3);
+        }
+        let glider = Format::Macrocell.parse(&deep).unwrap();
+        assert_eq!((glider.width, glider.height, glider.cells.len()), (1, 1, 1));
+    }
+
+    #[test]
+    fn empty_patterns_survive_a_round_trip() {
+        for format in FORMATS {
+            let parsed = format.parse(&format.write(&Pattern::default()).unwrap()).unwrap();
+            assert!(parsed.cells.is_empty(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn two_state_formats_refuse_more_states() {
+        let clock = sample("wireworld_clock.rle");
+        for format in [Format::Plaintext, Format::Life105, Format::Life106] {
+            assert!(matches!(format.write(&clock), Err(PatternError::Unsupported(_))), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_files_report_the_line() {
+        assert_eq!(syntax_error_line(Format::Rle, "#N Glider\nbob$2bo$3o!"), 2);
+        assert_eq!(syntax_error_line(Format::Rle, "x = 3, y = 3\nbob$2bo$3q!"), 2);
+        assert_eq!(syntax_error_line(Format::Rle, "x = 3\nbob!"), 1);
+        assert_eq!(syntax_error_line(Format::Plaintext, "!Name: Glider\n.O.\n..X"), 3);
+        assert_eq!(syntax_error_line(Format::Life105, "#Life 1.05\n#P 0 zero\n*"), 2);
+        assert_eq!(syntax_error_line(Format::Life105, "#Life 1.05\n#P 0 0\n.*o"), 3);
+        assert_eq!(syntax_error_line(Format::Life106, "#Life 1.05\n0 0"), 1);
+        assert_eq!(syntax_error_line(Format::Life106, "#Life 1.06\n0 0\n1"), 3);
+        assert_eq!(syntax_error_line(Format::Life106, "#Life 1.06\n0 0 0"), 2);
+        assert_eq!(syntax_error_line(Format::Macrocell, "#R B3/S23\n.*$"), 1);
+        assert_eq!(syntax_error_line(Format::Macrocell, "[M2]\n.........*$"), 2);
+        assert_eq!(syntax_error_line(Format::Macrocell, "[M2]\n.*$\n4 2 0 0 0"), 3);
+        assert_eq!(syntax_error_line(Format::Macrocell, "[M2]\n.*$\n5 1 0 0 0"), 3);
+        assert_eq!(syntax_error_line(Format::Macrocell, "[M2]\n1 1 0 0"), 2);
+        assert!(matches!(Pattern::load("glider.png"), Err(PatternError::UnknownFormat(_))));
+    }
+
+    #[test]
+    fn life_files_are_told_apart_by_their_header() {
+        let life = Format::from_extension("LIFE").unwrap();
+        assert_eq!(life.detect("#Life 1.05\n*"), Format::Life105);
+        assert_eq!(life.detect("#Life 1.06\n0 0"), Format::Life106);
+        assert_eq!(Format::Plaintext.detect("#Life 1.05"), Format::Plaintext);
+    }
+}
@@ -0,0 +1,78 @@
+//! The plaintext (`.cells`) pattern format.
+//!
+//! ```text
+//! !Name: Glider
+//! .O.
+//! ..O
+//! OOO
+//! ```
+//!
+//! Lines starting with `!` are comments, `!Name:` gives the name. Every
+//! other line is a row of `.` for dead and `O` for live cells. Rows may be
+//! shorter than the pattern, the missing cells are dead.
+use super::{Pattern, PatternError};
+
+/// Reads a plaintext pattern. `*` is accepted for live cells as well, as
+/// some older files use it.
+pub fn parse(text: &str) -> Result<Pattern, PatternError> {
+    let mut pattern = Pattern::default();
+    let mut y = 0;
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = raw.trim_end();
+        if let Some(comment) = line.strip_prefix('!') {
+            match comment.strip_prefix("Name:") {
+                Some(name) => pattern.name = Some(name.trim().to_string()),
+                None => pattern.comments.push(comment.trim().to_string()),
+            }
+            continue;
+        }
+
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '.' => (),
+                'O' | '*' => pattern.cells.push((x as u32, y, 1)),
+                _ => {
+                    return Err(PatternError::Syntax {
+                        line: index + 1,
+                        message: format!("unexpected character {c:?}, expected '.' or 'O'"),
+                    })
+                }
+            }
+        }
+        pattern.width = pattern.width.max(line.chars().count() as u32);
+        y += 1;
+    }
+
+    pattern.height = y;
+    Ok(pattern)
+}
+
+/// Writes a pattern as plaintext. Rows are written in full, so that the
+/// size of the pattern survives the round trip.
+pub fn write(pattern: &Pattern) -> Result<String, PatternError> {
+    if pattern.max_state() > 1 {
+        return Err(PatternError::Unsupported(
+            "plaintext patterns can only store two states".to_string(),
+        ));
+    }
+
+    let mut out = String::new();
+    if let Some(name) = &pattern.name {
+        out += &format!("!Name: {name}\n");
+    }
+    for comment in &pattern.comments {
+        out += &format!("!{comment}\n");
+    }
+
+    let width = pattern.width as usize;
+    let mut rows = vec![vec!['.'; width]; pattern.height as usize];
+    for &(x, y, _) in &pattern.cells {
+        rows[y as usize][x as usize] = 'O';
+    }
+    for row in rows {
+        out.extend(row);
+        out.push('\n');
+    }
+    Ok(out)
+}
@@ -0,0 +1,229 @@
+//! The Run Length Encoded (`.rle`) pattern format.
+//!
+//! ```text
+//! #N Glider
+//! x = 3, y = 3, rule = B3/S23
+//! bob$2bo$3o!
+//! ```
+//!
+//! Two-state patterns use `b` for dead and `o` for live cells. Multi-state
+//! patterns use `.` for state 0, `A` to `X` for states 1 to 24, and `pA` to
+//! `yO` for the states above. A number in front of a symbol repeats it, `$`
+//! ends a row and `!` ends the pattern.
+use super::{Pattern, PatternError};
+use crate::cli::MAX_GRID_SIZE;
+
+/// Lines of cell data are wrapped at this width, as most tools do.
+const LINE_WIDTH: usize = 70;
+
+/// The state written as `symbol`, optionally preceded by `prefix` (`p` to `y`).
+fn multi_state(prefix: Option<char>, symbol: char) -> Option<u32> {
+    let base = match prefix {
+        None => 0,
+        Some(p @ 'p'..='y') => (p as u32 - 'p' as u32 + 1) * 24,
+        Some(_) => return None,
+    };
+    match symbol {
+        'A'..='X' => Some(base + symbol as u32 - 'A' as u32 + 1),
+        _ => None,
+    }
+}
+
+/// The symbol for `state` in multi-state RLE.
+fn multi_state_symbol(state: u32) -> String {
+    if state == 0 {
+        return ".".to_string();
+    }
+    let letter = char::from(b'A' + ((state - 1) % 24) as u8);
+    match (state - 1) / 24 {
+        0 => letter.to_string(),
+        prefix => format!("{}{letter}", char::from(b'p' + (prefix - 1) as u8)),
+    }
+}
+
+/// The end of a run of `run` cells starting at `at`, if the pattern still fits
+/// on the largest grid.
+fn run_end(at: u32, run: u32) -> Option<u32> {
+    at.checked_add(run).filter(|&end| end <= MAX_GRID_SIZE)
+}
+
+/// Parses the `x = 3, y = 3, rule = B3/S23` header line.
+fn parse_header(pattern: &mut Pattern, header: &str, line: usize) -> Result<(), PatternError> {
+    let syntax = |message: String| PatternError::Syntax { line, message };
+    let mut size = (None, None);
+    for item in header.split(',') {
+        let (key, value) = item
+            .split_once('=')
+            .ok_or_else(|| syntax(format!("expected 'key = value' in header, found {item:?}")))?;
+        let value = value.trim();
+        let number = || {
+            match value.parse::<u32>() {
+                Ok(n) if n <= MAX_GRID_SIZE => Ok(n),
+                _ => Err(syntax(format!("invalid pattern size {value:?}, expected 0 to {MAX_GRID_SIZE}"))),
+            }
+        };
+        match key.trim() {
+            "x" => size.0 = Some(number()?),
+            "y" => size.1 = Some(number()?),
+            "rule" => pattern.rule = Some(value.to_string()),
+            // Other keys, such as Golly's `#CXRLE` extras, are not needed here.
+            _ => (),
+        }
+    }
+    match size {
+        (Some(width), Some(height)) => {
+            pattern.width = width;
+            pattern.height = height;
+            Ok(())
+        }
+        _ => Err(syntax("header needs both 'x' and 'y'".to_string())),
+    }
+}
+
+/// Reads an RLE pattern. Cells beyond the size given in the header grow the pattern.
+pub fn parse(text: &str) -> Result<Pattern, PatternError> {
+    let mut pattern = Pattern::default();
+    let mut header_seen = false;
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut count: Option<u32> = None;
+    let mut prefix: Option<char> = None;
+
+    'lines: for (index, raw) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let syntax = |message: String| PatternError::Syntax { line: line_no, message };
+        let line = raw.trim();
+
+        if !header_seen {
+            if let Some(comment) = line.strip_prefix('#') {
+                let mut chars = comment.chars();
+                let kind = chars.next();
+                let text = chars.as_str().trim().to_string();
+                match kind {
+                    Some('N') => pattern.name = Some(text),
+                    Some('C' | 'c' | 'O') => pattern.comments.push(text),
+                    _ => (),
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            if !line.starts_with('x') {
+                return Err(syntax("expected the 'x = ..., y = ...' header".to_string()));
+            }
+            parse_header(&mut pattern, line, line_no)?;
+            header_seen = true;
+            continue;
+        }
+
+        for c in line.chars() {
+            if c.is_ascii_whitespace() {
+                continue;
+            }
+            if let Some(p) = prefix.filter(|_| !c.is_ascii_uppercase()) {
+                return Err(syntax(format!("expected a state letter after {p:?}")));
+            }
+            if let Some(digit) = c.to_digit(10) {
+                let n = count.unwrap_or(0).checked_mul(10).and_then(|n| n.checked_add(digit));
+                count = Some(n.ok_or_else(|| syntax("run count is too large".to_string()))?);
+                continue;
+            }
+
+            let run = count.take().unwrap_or(1);
+            let too_large = || syntax(format!("the pattern is larger than the largest grid, {MAX_GRID_SIZE} cells across"));
+            let state = match c {
+                '!' => break 'lines,
+                '$' => {
+                    y = run_end(y, run).ok_or_else(too_large)?;
+                    x = 0;
+                    continue;
+                }
+                'p'..='y' => {
+                    prefix = Some(c);
+                    count = Some(run);
+                    continue;
+                }
+                'b' | '.' => 0,
+                'o' => 1,
+                _ => multi_state(prefix.take(), c)
+                    .ok_or_else(|| syntax(format!("unexpected character {c:?}")))?,
+            };
+
+            let end = run_end(x, run).ok_or_else(too_large)?;
+            if state != 0 {
+                let bottom = run_end(y, 1).ok_or_else(too_large)?;
+                pattern.cells.extend((x..end).map(|x| (x, y, state)));
+                pattern.width = pattern.width.max(end);
+                pattern.height = pattern.height.max(bottom);
+            }
+            x = end;
+        }
+    }
+
+    if !header_seen {
+        return Err(PatternError::Syntax {
+            line: text.lines().count().max(1),
+            message: "missing the 'x = ..., y = ...' header".to_string(),
+        });
+    }
+    Ok(pattern)
+}
+
+/// Writes a pattern as RLE, using multi-state symbols if any cell has a state above 1.
+pub fn write(pattern: &Pattern) -> String {
+    let mut out = String::new();
+    if let Some(name) = &pattern.name {
+        out += &format!("#N {name}\n");
+    }
+    for comment in &pattern.comments {
+        out += &format!("#C {comment}\n");
+    }
+    out += &format!("x = {}, y = {}", pattern.width, pattern.height);
+    if let Some(rule) = &pattern.rule {
+        out += &format!(", rule = {rule}");
+    }
+    out.push('\n');
+
+    let multi = pattern.max_state() > 1;
+    let symbol = |state: u32| match (multi, state) {
+        (true, _) => multi_state_symbol(state),
+        (false, 0) => "b".to_string(),
+        (false, _) => "o".to_string(),
+    };
+
+    // Runs of (count, symbol), with trailing dead cells dropped and empty rows merged.
+    let mut runs: Vec<(u32, String)> = Vec::new();
+    let push = |runs: &mut Vec<(u32, String)>, count: u32, symbol: String| match runs.last_mut() {
+        Some((n, last)) if *last == symbol => *n += count,
+        _ => runs.push((count, symbol)),
+    };
+    let mut cells = pattern.cells.clone();
+    cells.sort_by_key(|&(x, y, _)| (y, x));
+    let (mut x, mut y) = (0, 0);
+    for (cx, cy, state) in cells {
+        if cy > y {
+            push(&mut runs, cy - y, "$".to_string());
+            (x, y) = (0, cy);
+        }
+        if cx > x {
+            push(&mut runs, cx - x, symbol(0));
+        }
+        push(&mut runs, 1, symbol(state));
+        x = cx + 1;
+    }
+    runs.push((1, "!".to_string()));
+
+    let mut line = String::new();
+    for (count, symbol) in runs {
+        let item = if count == 1 { symbol } else { format!("{count}{symbol}") };
+        if line.len() + item.len() > LINE_WIDTH {
+            out += &line;
+            out.push('\n');
+            line.clear();
+        }
+        line += &item;
+    }
+    out += &line;
+    out.push('\n');
+    out
+}
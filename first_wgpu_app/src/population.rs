@@ -0,0 +1,244 @@
+//! Counting the live cells of each generation on the GPU.
+//!
+//! Each count comes with a hash of the generation, see [`Census`]. Counts are
+//! copied into small staging buffers that are mapped
+//! asynchronously, so neither drawing nor simulating waits for them. Results
+//! come in a frame or so later, whenever [`PopulationCounter::collect`] finds
+//! them ready.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    mem,
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use crate::world::World;
+
+/// What the GPU found out about a generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Census {
+    pub generation: u64,
+    /// The number of non-dead cells.
+    pub population: u32,
+    /// A hash of all the cells, equal for equal generations of the same world.
+    pub hash: u64,
+}
+
+/// A count that has been recorded but not submitted yet.
+#[must_use = "pass the count to `PopulationCounter::submitted` after submitting its commands"]
+pub struct PendingCount {
+    generation: u64,
+    buffer: wgpu::Buffer,
+}
+
+/// A staging buffer whose mapping finished, successfully or not.
+struct Reading {
+    generation: u64,
+    buffer: wgpu::Buffer,
+    mapped: Result<(), wgpu::BufferAsyncError>,
+}
+
+pub struct PopulationCounter {
+    /// Staging buffers not waiting for a count, ready to be reused.
+    idle: Vec<wgpu::Buffer>,
+    sender: Sender<Reading>,
+    receiver: Receiver<Reading>,
+    /// The generation counted last, and its population.
+    latest: Option<(u64, u32)>,
+    /// Where the population history goes as CSV, if anywhere.
+    csv: Option<Box<dyn Write>>,
+    /// The population of every generation counted but not written out yet. A
+    /// generation counted again, e.g. after editing it while paused, keeps
+    /// the last count.
+    unwritten: BTreeMap<u64, u32>,
+}
+
+impl Default for PopulationCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PopulationCounter {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        PopulationCounter {
+            idle: Vec::new(),
+            sender,
+            receiver,
+            latest: None,
+            csv: None,
+            unwritten: BTreeMap::new(),
+        }
+    }
+
+    /// Writes the population history to a new CSV file at `path`, starting
+    /// with a header line. Until then only the latest count is kept.
+    pub fn write_to(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut csv = BufWriter::new(File::create(path)?);
+        writeln!(csv, "generation,population")?;
+        self.csv = Some(Box::new(csv));
+        Ok(())
+    }
+
+    /// Records counting the current generation of `world` into `encoder`.
+    pub fn record(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        world: &World,
+    ) -> PendingCount {
+        let buffer = self.idle.pop().unwrap_or_else(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Population download"),
+                size: World::CENSUS_SIZE,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        });
+        world.take_census(encoder, &buffer);
+        PendingCount {
+            generation: world.generation(),
+            buffer,
+        }
+    }
+
+    /// Starts reading back a count once the commands recording it have been submitted.
+    pub fn submitted(&self, pending: PendingCount) {
+        let PendingCount { generation, buffer } = pending;
+        let sender = self.sender.clone();
+        let mapped_buffer = buffer.clone();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |mapped| {
+            // The counter may be gone by now, in which case nobody wants the count.
+            let _ = sender.send(Reading {
+                generation,
+                buffer: mapped_buffer,
+                mapped,
+            });
+        });
+    }
+
+    /// Takes in the counts that are ready, without waiting for the others,
+    /// and returns them in the order they were recorded.
+    pub fn collect(&mut self, device: &wgpu::Device) -> Vec<Census> {
+        device.poll(wgpu::Maintain::Poll);
+        let mut collected = Vec::new();
+        while let Ok(reading) = self.receiver.try_recv() {
+            match reading.mapped {
+                Ok(()) => {
+                    let data = reading.buffer.slice(..).get_mapped_range();
+                    let [population, low, high] = bytemuck::pod_read_unaligned::<[u32; 3]>(&data);
+                    drop(data);
+                    reading.buffer.unmap();
+                    self.idle.push(reading.buffer);
+                    self.counted(reading.generation, population);
+                    collected.push(Census {
+                        generation: reading.generation,
+                        population,
+                        hash: u64::from(high) << 32 | u64::from(low),
+                    });
+                }
+                Err(e) => log::warn!("Cannot read the population of generation {}: {e}", reading.generation),
+            }
+        }
+        collected
+    }
+
+    fn counted(&mut self, generation: u64, population: u32) {
+        self.latest = Some((generation, population));
+        if self.csv.is_some() {
+            self.unwritten.insert(generation, population);
+        }
+    }
+
+    /// The generation counted last, and its population.
+    pub fn latest(&self) -> Option<(u64, u32)> {
+        self.latest
+    }
+
+    /// Writes out the counts of the generations before `generation`, which
+    /// must not be counted again, and forgets them. Once writing fails the
+    /// history is dropped.
+    pub fn write_before(&mut self, generation: u64) -> io::Result<()> {
+        let Some(csv) = &mut self.csv else {
+            return Ok(());
+        };
+        let later = self.unwritten.split_off(&generation);
+        let written = mem::replace(&mut self.unwritten, later)
+            .into_iter()
+            .try_for_each(|(generation, population)| writeln!(csv, "{generation},{population}"));
+        if written.is_err() {
+            self.csv = None;
+            self.unwritten.clear();
+        }
+        written
+    }
+
+    /// Writes out the counts of all generations left, once nothing more will
+    /// be counted.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.write_before(u64::MAX)?;
+        match self.csv.take() {
+            Some(mut csv) => csv.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A counter writing to a temporary file named after the test.
+    fn writing(name: &str) -> (PopulationCounter, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("first_wgpu_app-{}-{name}.csv", std::process::id()));
+        let mut counter = PopulationCounter::new();
+        counter.write_to(&path).unwrap();
+        (counter, path)
+    }
+
+    fn finish(mut counter: PopulationCounter, path: &Path) -> String {
+        counter.finish().unwrap();
+        let csv = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        csv
+    }
+
+    #[test]
+    fn writes_the_last_count_of_each_generation_in_order() {
+        // Rewound to generation 1, edited, and stepped again.
+        let (mut counter, path) = writing("order");
+        for (generation, population) in [(0, 5), (1, 6), (2, 7), (1, 3), (2, 4)] {
+            counter.counted(generation, population);
+        }
+        assert_eq!(counter.latest(), Some((2, 4)));
+        assert_eq!(finish(counter, &path), "generation,population\n0,5\n1,3\n2,4\n");
+    }
+
+    #[test]
+    fn forgets_counts_once_written_out() {
+        let (mut counter, path) = writing("forget");
+        (0..4).for_each(|generation| counter.counted(generation, 10 + generation as u32));
+        counter.write_before(2).unwrap();
+        assert_eq!(counter.unwritten.keys().copied().collect::<Vec<_>>(), [2, 3]);
+
+        counter.counted(3, 20);
+        counter.write_before(4).unwrap();
+        assert!(counter.unwritten.is_empty());
+        counter.counted(4, 21);
+        assert_eq!(finish(counter, &path), "generation,population\n0,10\n1,11\n2,12\n3,20\n4,21\n");
+    }
+
+    #[test]
+    fn keeps_only_the_latest_count_without_a_file() {
+        let mut counter = PopulationCounter::new();
+        (0..100).for_each(|generation| counter.counted(generation, 1));
+        assert_eq!(counter.latest(), Some((99, 1)));
+        assert!(counter.unwritten.is_empty());
+        counter.finish().unwrap();
+    }
+}
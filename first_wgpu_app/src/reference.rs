@@ -0,0 +1,106 @@
+//! A CPU implementation of the compute kernels, for checking them.
+//!
+//! It follows `c_shader.wgsl` cell by cell but shares none of its tricks:
+//! no workgroup tiles, no packed words, and rule tables are matched against
+//! their transitions rather than the bitsets uploaded to the GPU.
+use crate::{boundary::Boundary, rule::Automaton, table::Neighborhood};
+
+/// Offsets of the neighborhoods in the order rule tables list their inputs,
+/// with y pointing up like in the cell buffers.
+const MOORE: [(i64, i64); 9] = [
+    (0, 0), (0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1),
+];
+const VON_NEUMANN: [(i64, i64); 5] = [(0, 0), (0, 1), (1, 0), (0, -1), (-1, 0)];
+
+/// A grid of cells stepped on the CPU. Cells are stored one state per cell,
+/// row 0 at the bottom, like the unpacked cell buffers.
+#[derive(Debug, Clone)]
+pub struct CpuWorld {
+    pub automaton: Automaton,
+    pub boundary: Boundary,
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<u32>,
+}
+
+impl CpuWorld {
+    pub fn new(automaton: Automaton, boundary: Boundary, width: u32, height: u32, cells: Vec<u32>) -> Self {
+        assert_eq!(cells.len(), width as usize * height as usize);
+        CpuWorld {
+            automaton,
+            boundary,
+            width,
+            height,
+            cells,
+        }
+    }
+
+    /// The state of the cell at `(x, y)`, which may lie one cell beyond the edges of the grid.
+    pub fn state(&self, x: i64, y: i64) -> u32 {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let outside_x = !(0..width).contains(&x);
+        let outside_y = !(0..height).contains(&y);
+
+        let (mut x, mut y) = (x, y);
+        match self.boundary {
+            Boundary::Torus => (),
+            Boundary::Dead if outside_x || outside_y => return 0,
+            Boundary::Dead => (),
+            Boundary::Mirror => (x, y) = (x.clamp(0, width - 1), y.clamp(0, height - 1)),
+            Boundary::Klein => {
+                if outside_y {
+                    x = width - 1 - x;
+                }
+            }
+            Boundary::Cross => {
+                if outside_y {
+                    x = width - 1 - x;
+                }
+                if outside_x {
+                    y = height - 1 - y;
+                }
+            }
+        }
+        let (x, y) = (x.rem_euclid(width), y.rem_euclid(height));
+        self.cells[(y * width + x) as usize]
+    }
+
+    fn next_state(&self, x: i64, y: i64) -> u32 {
+        let state = self.state(x, y);
+        match &self.automaton {
+            Automaton::Totalistic(rule) => {
+                // Only fully alive cells count as neighbors.
+                let neighbors = MOORE[1..]
+                    .iter()
+                    .filter(|&&(dx, dy)| self.state(x + dx, y + dy) == 1)
+                    .count();
+                if state == 0 {
+                    (rule.birth >> neighbors) & 1
+                } else if state == 1 && (rule.survival >> neighbors) & 1 == 1 {
+                    1
+                } else {
+                    (state + 1) % rule.states
+                }
+            }
+            Automaton::Table(table) => {
+                let offsets = match table.neighborhood {
+                    Neighborhood::Moore => &MOORE[..],
+                    Neighborhood::VonNeumann => &VON_NEUMANN[..],
+                };
+                let neighborhood: Vec<u32> = offsets
+                    .iter()
+                    .map(|&(dx, dy)| self.state(x + dx, y + dy))
+                    .collect();
+                table.next_state(&neighborhood)
+            }
+        }
+    }
+
+    /// Advances the grid by one generation.
+    pub fn step(&mut self) {
+        let width = self.width as i64;
+        self.cells = (0..self.cells.len() as i64)
+            .map(|i| self.next_state(i % width, i / width))
+            .collect();
+    }
+}
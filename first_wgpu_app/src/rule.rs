@@ -0,0 +1,287 @@
+//! Life-like ("outer totalistic") rules written in B/S notation.
+//!
+//! A rulestring such as `B36/S23` lists the neighbor counts for which a dead
+//! cell is born (`B`) and for which a live cell survives (`S`). Each list is
+//! turned into a 9-bit mask, where bit `n` is set when `n` live neighbors
+//! trigger the transition, so the compute shader can test a count with a
+//! single shift.
+//!
+//! An optional `C` part turns the rule into a "Generations" rule: `B2/S/C3`
+//! (Brian's Brain) has three states, and a live cell that does not survive
+//! passes through the decay states `2..C` before it is dead again. Only
+//! cells in state 1 count as live neighbors.
+use std::{fmt, str::FromStr};
+
+use crate::table::RuleTable;
+
+/// The largest number of live neighbors a cell can have in the Moore neighborhood.
+const MAX_NEIGHBORS: u32 = 8;
+
+/// The largest number of states a Generations rule or a rule table may have.
+pub const MAX_STATES: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    /// Bit `n` is set if a dead cell with `n` live neighbors is born.
+    pub birth: u32,
+    /// Bit `n` is set if a live cell with `n` live neighbors survives.
+    pub survival: u32,
+    /// Number of cell states, including dead and live. Life-like rules have 2.
+    pub states: u32,
+}
+
+impl Rule {
+    /// Conway's Game of Life, `B3/S23`.
+    pub const CONWAY: Rule = Rule {
+        birth: 1 << 3,
+        survival: (1 << 2) | (1 << 3),
+        states: 2,
+    };
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::CONWAY
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    /// The rulestring did not have exactly one `B` part and one `S` part.
+    MissingPart(char),
+    /// The same part (`B`, `S` or `C`) was given twice.
+    DuplicatePart(char),
+    /// A part started with something other than `B`, `S` or `C`.
+    UnknownPart(String),
+    /// A neighbor count was not a digit in `0..=8`.
+    InvalidCount(char),
+    /// The number of states was not a number in `2..=MAX_STATES`.
+    InvalidStates(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::MissingPart(p) => write!(f, "rulestring has no '{p}' part"),
+            RuleError::DuplicatePart(p) => write!(f, "rulestring has more than one '{p}' part"),
+            RuleError::UnknownPart(s) => {
+                write!(f, "unknown rulestring part {s:?}, expected 'B...', 'S...' or 'C...'")
+            }
+            RuleError::InvalidCount(c) => {
+                write!(f, "invalid neighbor count {c:?}, expected a digit from 0 to {MAX_NEIGHBORS}")
+            }
+            RuleError::InvalidStates(s) => {
+                write!(f, "invalid number of states {s:?}, expected a number from 2 to {MAX_STATES}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// Turns a list of neighbor counts like `"236"` into a bitmask.
+fn parse_counts(counts: &str) -> Result<u32, RuleError> {
+    counts.chars().try_fold(0, |mask, c| match c.to_digit(10) {
+        Some(n) if n <= MAX_NEIGHBORS => Ok(mask | (1 << n)),
+        _ => Err(RuleError::InvalidCount(c)),
+    })
+}
+
+/// Parses the number of states of a Generations rule.
+fn parse_states(states: &str) -> Result<u32, RuleError> {
+    match states.parse() {
+        Ok(n) if (2..=MAX_STATES).contains(&n) => Ok(n),
+        _ => Err(RuleError::InvalidStates(states.to_string())),
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    /// Parses `B<counts>/S<counts>[/C<states>]` (parts in any order,
+    /// case-insensitive), or the traditional `<survival>/<birth>[/<states>]`
+    /// form such as `23/3` or `345/2/4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parts: Vec<&str> = s.split('/').map(str::trim).collect();
+
+        // Traditional notation has no letters at all: survival first, then birth.
+        let is_numeric = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (2..=3).contains(&parts.len()) && parts.iter().all(|part| is_numeric(part)) {
+            return Ok(Rule {
+                birth: parse_counts(parts[1])?,
+                survival: parse_counts(parts[0])?,
+                states: parts.get(2).map_or(Ok(2), |states| parse_states(states))?,
+            });
+        }
+
+        let mut birth = None;
+        let mut survival = None;
+        let mut states = None;
+        for part in parts {
+            let mut chars = part.chars();
+            let name = chars.next().map(|c| c.to_ascii_uppercase());
+            let (slot, value) = match name {
+                Some('B') => (&mut birth, parse_counts(chars.as_str())?),
+                Some('S') => (&mut survival, parse_counts(chars.as_str())?),
+                Some('C') => (&mut states, parse_states(chars.as_str())?),
+                _ => return Err(RuleError::UnknownPart(part.to_string())),
+            };
+            if slot.is_some() {
+                return Err(RuleError::DuplicatePart(name.unwrap()));
+            }
+            *slot = Some(value);
+        }
+
+        Ok(Rule {
+            birth: birth.ok_or(RuleError::MissingPart('B'))?,
+            survival: survival.ok_or(RuleError::MissingPart('S'))?,
+            states: states.unwrap_or(2),
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    /// Writes the rule in canonical `B.../S...[/C...]` form.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = |mask: u32| -> String {
+            (0..=MAX_NEIGHBORS)
+                .filter(|n| mask & (1 << n) != 0)
+                .map(|n| char::from_digit(n, 10).unwrap())
+                .collect()
+        };
+        write!(f, "B{}/S{}", counts(self.birth), counts(self.survival))?;
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything the simulation can run: a totalistic rule, or a rule table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Automaton {
+    Totalistic(Rule),
+    Table(RuleTable),
+}
+
+impl Automaton {
+    /// Reads the automaton named on the command line: a path to a `.rule`
+    /// file, the built-in `wireworld`, or a rulestring.
+    pub fn from_spec(spec: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if spec.ends_with(".rule") {
+            Ok(Automaton::Table(RuleTable::load(spec)?))
+        } else if spec.eq_ignore_ascii_case("wireworld") {
+            Ok(Automaton::Table(RuleTable::wireworld()))
+        } else {
+            Ok(Automaton::Totalistic(spec.parse()?))
+        }
+    }
+
+    pub fn states(&self) -> u32 {
+        match self {
+            Automaton::Totalistic(rule) => rule.states,
+            Automaton::Table(table) => table.states,
+        }
+    }
+
+    /// The contents of the rule uniform buffer, laid out like `Rule` in the shaders.
+    /// The last word tells the shaders whether a rule table is in use.
+    pub fn uniform_data(&self) -> [u32; 4] {
+        match self {
+            Automaton::Totalistic(rule) => [rule.birth, rule.survival, rule.states, 0],
+            Automaton::Table(table) => [0, 0, table.states, 1],
+        }
+    }
+
+    /// The contents of the table storage buffer. Totalistic rules get an
+    /// empty table, as the binding must exist either way.
+    pub fn table_data(&self) -> Vec<u32> {
+        match self {
+            Automaton::Totalistic(_) => vec![0; 4],
+            Automaton::Table(table) => table.buffer_data(),
+        }
+    }
+}
+
+impl Default for Automaton {
+    fn default() -> Self {
+        Automaton::Totalistic(Rule::CONWAY)
+    }
+}
+
+impl fmt::Display for Automaton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Automaton::Totalistic(rule) => rule.fmt(f),
+            Automaton::Table(table) => f.write_str(&table.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(s: &str) -> Rule {
+        s.parse().unwrap_or_else(|e| panic!("cannot parse {s:?}: {e}"))
+    }
+
+    #[test]
+    fn parses_birth_and_survival_masks() {
+        assert_eq!(rule("B3/S23"), Rule::CONWAY);
+        assert_eq!(rule("B36/S23"), Rule { birth: 0b1001000, survival: 0b1100, states: 2 });
+        assert_eq!(rule("B/S012345678"), Rule { birth: 0, survival: 0x1ff, states: 2 });
+        // Parts in any order, in either case, with whitespace around them.
+        assert_eq!(rule(" s23 / b3 "), Rule::CONWAY);
+        // Traditional notation lists survival first.
+        assert_eq!(rule("23/3"), Rule::CONWAY);
+        assert_eq!(rule("/3"), Rule { birth: 1 << 3, survival: 0, states: 2 });
+    }
+
+    #[test]
+    fn displays_the_canonical_form() {
+        for (input, canonical) in [("B3/S23", "B3/S23"), ("s32/b63", "B36/S23"), ("23/3", "B3/S23"), ("B/S", "B/S")] {
+            assert_eq!(rule(input).to_string(), canonical);
+            assert_eq!(rule(canonical), rule(input));
+        }
+    }
+
+    #[test]
+    fn parses_generations_rules() {
+        let brians_brain = Rule { birth: 1 << 2, survival: 0, states: 3 };
+        assert_eq!(rule("B2/S/C3"), brians_brain);
+        assert_eq!(rule("c3/S/b2"), brians_brain);
+        // The traditional form gives the number of states last.
+        assert_eq!(rule("/2/3"), brians_brain);
+        assert_eq!(brians_brain.to_string(), "B2/S/C3");
+        // Two states is an ordinary Life-like rule.
+        assert_eq!(rule("B3/S23/C2"), Rule::CONWAY);
+        assert_eq!(rule("B3/S23/C2").to_string(), "B3/S23");
+        assert_eq!(rule(&format!("B3/S23/C{MAX_STATES}")).states, MAX_STATES);
+    }
+
+    #[test]
+    fn rejects_invalid_numbers_of_states() {
+        let error = |s: &str| s.parse::<Rule>().unwrap_err();
+        for states in ["1", "0", "257", "", "x", "-3"] {
+            assert_eq!(error(&format!("B2/S/C{states}")), RuleError::InvalidStates(states.to_string()));
+        }
+        assert_eq!(error("B2/S/C3/C4"), RuleError::DuplicatePart('C'));
+        assert_eq!(error("345/2/1"), RuleError::InvalidStates("1".to_string()));
+    }
+
+    #[test]
+    fn rejects_malformed_rulestrings() {
+        let error = |s: &str| s.parse::<Rule>().unwrap_err();
+        assert_eq!(error("B3"), RuleError::MissingPart('S'));
+        assert_eq!(error("S23"), RuleError::MissingPart('B'));
+        assert_eq!(error("B3/S23/B4"), RuleError::DuplicatePart('B'));
+        assert_eq!(error("B3/s2/S3"), RuleError::DuplicatePart('S'));
+        assert_eq!(error("B3/X23"), RuleError::UnknownPart("X23".to_string()));
+        assert_eq!(error(""), RuleError::UnknownPart(String::new()));
+        assert_eq!(error("B39/S23"), RuleError::InvalidCount('9'));
+        assert_eq!(error("B3/S2a"), RuleError::InvalidCount('a'));
+        assert_eq!(error("23/9"), RuleError::InvalidCount('9'));
+    }
+}
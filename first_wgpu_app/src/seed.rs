@@ -0,0 +1,175 @@
+//! The initial generation.
+//!
+//! Random generators draw from a PRNG seeded with `--seed`, so a run started
+//! with the same seed, options and build of the program starts from exactly
+//! the same cells.
+use std::{fmt, str::FromStr};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{cells, cli::Options, rule::Automaton};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Generator {
+    /// Random cells all over the grid.
+    #[default]
+    Random,
+    /// A square of random cells in the middle of an otherwise dead grid.
+    Blob,
+    /// Like `Blob`, but mirrored left to right and top to bottom, which makes
+    /// soups more likely to grow into large symmetric patterns.
+    Symmetric,
+    /// The pattern file given with `--pattern`.
+    Pattern,
+}
+
+impl Generator {
+    pub const ALL: [Generator; 4] = [
+        Generator::Random,
+        Generator::Blob,
+        Generator::Symmetric,
+        Generator::Pattern,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Generator::Random => "random",
+            Generator::Blob => "blob",
+            Generator::Symmetric => "symmetric",
+            Generator::Pattern => "pattern",
+        }
+    }
+
+    /// Whether the generator draws from the PRNG, so that its seed matters.
+    pub fn is_random(&self) -> bool {
+        *self != Generator::Pattern
+    }
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Generator::ALL
+            .into_iter()
+            .find(|g| g.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Generator::ALL.iter().map(Generator::name).collect();
+                format!("unknown generator {s:?}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The contents of the cell buffer for the first generation: one state per
+/// cell, or 32 cells per word for the packed layout.
+pub fn initial_cells(options: &Options) -> Vec<u32> {
+    let (width, height) = options.grid_size;
+    let cell_count = width as usize * height as usize;
+    let mut cells = vec![0; if options.packed { cell_count / 32 } else { cell_count }];
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    // Rule tables have no special "alive" state, so they are seeded with any non-empty state.
+    let max_state = match options.automaton {
+        Automaton::Totalistic(_) => 1,
+        Automaton::Table(_) => options.automaton.states() - 1,
+    };
+    let mut random_state = || {
+        if rng.random_bool(options.density) {
+            rng.random_range(1..=max_state)
+        } else {
+            0
+        }
+    };
+    let mut set = |cell, state| cells::set(&mut cells, width, options.packed, cell, state);
+
+    // The square holding the soup of `Blob` and `Symmetric`, cut down to the
+    // grid along an axis where it does not fit.
+    let soup = (options.soup_size.min(width), options.soup_size.min(height));
+    let corner = ((width - soup.0) / 2, (height - soup.1) / 2);
+    match options.generator {
+        Generator::Random => {
+            for y in 0..height {
+                for x in 0..width {
+                    set((x, y), random_state());
+                }
+            }
+        }
+        Generator::Blob => {
+            for y in corner.1..corner.1 + soup.1 {
+                for x in corner.0..corner.0 + soup.0 {
+                    set((x, y), random_state());
+                }
+            }
+        }
+        Generator::Symmetric => {
+            // Draw one quarter and mirror it into the other three.
+            for dy in 0..soup.1.div_ceil(2) {
+                for dx in 0..soup.0.div_ceil(2) {
+                    let state = random_state();
+                    for x in [corner.0 + dx, corner.0 + soup.0 - 1 - dx] {
+                        for y in [corner.1 + dy, corner.1 + soup.1 - 1 - dy] {
+                            set((x, y), state);
+                        }
+                    }
+                }
+            }
+        }
+        Generator::Pattern => {
+            let pattern = options.pattern.as_ref().expect("the pattern generator needs a pattern");
+            let at = options.pattern_at.unwrap_or_else(|| cells::centered(width, height, pattern));
+            let dropped = cells::stamp(&mut cells, width, height, options.packed, pattern, at);
+            if dropped > 0 {
+                log::warn!("{dropped} cells of the pattern fall outside the grid and were dropped");
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn soup(generator: Generator, seed: u64) -> Vec<u32> {
+        initial_cells(&Options {
+            generator,
+            seed,
+            grid_size: (64, 48),
+            ..Options::default()
+        })
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_soup() {
+        for generator in [Generator::Random, Generator::Blob, Generator::Symmetric] {
+            let first = soup(generator, 42);
+            assert_eq!(first, soup(generator, 42), "{generator}");
+            assert_ne!(first, soup(generator, 43), "{generator}");
+            assert!(first.contains(&1), "{generator}");
+        }
+    }
+
+    #[test]
+    fn symmetric_soups_are_mirrored_both_ways() {
+        let cells = soup(Generator::Symmetric, 7);
+        let (width, height) = (64, 48);
+        let at = |x: usize, y: usize| cells[y * width + x];
+        // The default soup is 16 cells wide, in the middle of the grid.
+        let soup = |x: usize, y: usize| (24..40).contains(&x) && (16..32).contains(&y);
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(at(x, y), at(width - 1 - x, y));
+                assert_eq!(at(x, y), at(x, height - 1 - y));
+                if !soup(x, y) {
+                    assert_eq!(at(x, y), 0, "({x}, {y})");
+                }
+            }
+        }
+    }
+}
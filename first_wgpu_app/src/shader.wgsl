@@ -6,32 +6,258 @@ struct VertexInput {
 struct VertexOutput {
   @builtin(position) pos: vec4f,
   @location(0) cell: vec2f,
+  @location(1) @interpolate(flat) state: u32,
+  @location(2) @interpolate(flat) index: u32,
 };
 
 struct FragInput {
   @location(0) cell: vec2f,
+  @location(1) @interpolate(flat) state: u32,
+  @location(2) @interpolate(flat) index: u32,
 };
 
-@group(0) @binding(0) var<uniform> grid: vec2f;
+struct Rule {
+  birth: u32,
+  survival: u32,
+  states: u32,
+  table: u32,
+};
+
+struct Grid {
+  size: vec2f,
+  boundary: u32,
+  packed: u32,
+};
+
+// The view of the grid, see camera.rs.
+struct Camera {
+  // The point of the grid in the middle of the window, in cells.
+  center: vec2f,
+  // The width and height of a cell in clip space.
+  scale: vec2f,
+};
+
+@group(0) @binding(0) var<uniform> grid: Grid;
 @group(0) @binding(1) var<storage> cellState: array<u32>;
+@group(0) @binding(3) var<uniform> rule: Rule;
+@group(0) @binding(6) var<uniform> camera: Camera;
+
+// The most colors in a gradient, see `palette.rs`.
+const MAX_STOPS = 8u;
+
+// Evenly spaced colors, the first `count` of `colors`.
+struct Gradient {
+  count: u32,
+  colors: array<vec4f, MAX_STOPS>,
+};
+
+// How cells are colored, see `coloring.rs`, with the colors of the palette.
+struct Coloring {
+  mode: u32,
+  dead: vec4f,
+  // Live cells at the bottom left, bottom right, top left and top right
+  // corners of the grid.
+  alive: array<vec4f, 4>,
+  gradients: array<Gradient, 5>,
+};
+@group(0) @binding(7) var<uniform> coloring: Coloring;
+
+// The gradients of `Coloring`, in the order of `Palette::uniform_data`.
+const DYING_COLORS = 0u;
+const STATE_COLORS = 1u;
+const AGE_COLORS = 2u;
+const HEAT_COLORS = 3u;
+const NEIGHBOR_COLORS = 4u;
+
+const POSITION = 0u;
+const AGE = 1u;
+const HEAT = 2u;
+const NEIGHBORS = 3u;
+
+// The age, active neighbors and heat of every cell, laid out as described
+// with `track_cells` in c_shader.wgsl.
+@group(1) @binding(0) var<storage> activity: array<u32>;
+
+const MAX_HEAT = 0xfffu;
+
+// Whether the fullscreen renderer averages the cells sharing a pixel, set
+// when the pipeline is created.
+override SMOOTH: bool = false;
+// The most cells the fullscreen renderer averages along each axis of a pixel.
+const MAX_SAMPLES = 4u;
+
+// The state of the cell at `index`, counting rows from the bottom.
+fn stateAt(index: u32) -> u32 {
+  if grid.packed == 1u {
+    return (cellState[index / 32u] >> (index % 32u)) & 1u;
+  }
+  return cellState[index];
+}
 
 @vertex
 fn vertex_main(input: VertexInput) -> VertexOutput  {
-  let i = f32(input.instance);
-  let cell = vec2f(i % grid.x, floor(i / grid.x));
-  let state = f32(cellState[input.instance]); 
-  let cellOffset = cell / grid * 2;
-  let gridPos = (input.pos*state + 1) / grid - 1 + cellOffset;
-  
+  // Integer math keeps large grids exact, where f32 can no longer hold every index.
+  let width = u32(grid.size.x);
+  let cell = vec2f(f32(input.instance % width), f32(input.instance / width));
+  let state = stateAt(input.instance);
+  // Hidden cells collapse to a zero-area quad.
+  let visible = f32(isVisible(input.instance, state));
+  // The corner of the quad in cells, from the grid's bottom left corner.
+  let gridPos = cell + (input.pos * visible + 1) / 2;
+
   var output: VertexOutput;
-  output.pos = vec4f(gridPos, 0, 1);
+  output.pos = vec4f((gridPos - camera.center) * camera.scale, 0, 1);
   output.cell = cell; 
+  output.state = state;
+  output.index = input.instance;
   return output;
 }
 
+// The color at `t` in [0, 1] along gradient `which` of the palette.
+fn gradientColor(which: u32, t: f32) -> vec4f {
+    let count = coloring.gradients[which].count;
+    if count == 1u {
+      return coloring.gradients[which].colors[0];
+    }
+    let x = clamp(t, 0.0, 1.0) * f32(count - 1u);
+    let i = min(u32(x), count - 2u);
+    return mix(coloring.gradients[which].colors[i], coloring.gradients[which].colors[i + 1u], x - f32(i));
+}
+
+// `top` blended over `bottom`, as if drawn one after the other.
+fn over(top: vec4f, bottom: vec4f) -> vec4f {
+    let alpha = top.a + bottom.a * (1 - top.a);
+    if alpha == 0 {
+      return vec4f(0);
+    }
+    return vec4f((top.rgb * top.a + bottom.rgb * bottom.a * (1 - top.a)) / alpha, alpha);
+}
+
+// How recently cell `index` changed state, from 0 to 1 for the last generation.
+fn heatOf(index: u32) -> f32 {
+    return f32(activity[index] >> 20u) / f32(MAX_HEAT);
+}
+
+// Whether cell `index` in `state` is drawn: live and decaying cells are,
+// dead cells if the palette gives them a color, and in the heat map so are
+// dead cells that changed recently.
+fn isVisible(index: u32, state: u32) -> bool {
+    return state != 0u || coloring.dead.a > 0 || (coloring.mode == HEAT && heatOf(index) > 0);
+}
+
+// The color of the visible cell `index` at `cell` in `state`, with an alpha
+// below 1 for dead cells fading out of the heat map.
+fn cellColor(cell: vec2f, index: u32, state: u32) -> vec4f {
+    switch coloring.mode {
+      case HEAT: {
+        let heat = heatOf(index);
+        if state == 0u {
+          let glow = gradientColor(HEAT_COLORS, heat);
+          return over(vec4f(glow.rgb, glow.a * heat), coloring.dead);
+        }
+        // Live cells that have not changed in a while stay visible.
+        return gradientColor(HEAT_COLORS, max(heat, 0.1));
+      }
+      default: {}
+    }
+    if state == 0u {
+      return coloring.dead;
+    }
+    switch coloring.mode {
+      case AGE: {
+        // Cells go along the gradient as they get older, most quickly at first.
+        let age = f32(activity[index] & 0xffffu);
+        return gradientColor(AGE_COLORS, log2(age + 1) / 11);
+      }
+      case NEIGHBORS: {
+        let neighbors = (activity[index] >> 16u) & 0xfu;
+        return gradientColor(NEIGHBOR_COLORS, f32(neighbors) / 8);
+      }
+      default: {
+        return positionColor(cell, state);
+      }
+    }
+}
+
+// The color of a non-dead `cell` in `state` by its position on the grid.
+fn positionColor(cell: vec2f, state: u32) -> vec4f {
+    let progress = f32(state - 1u) / f32(max(rule.states, 2u) - 1u);
+    if rule.table == 1u {
+      // Rule tables have no notion of alive or decaying, give every state its own color.
+      return gradientColor(STATE_COLORS, progress);
+    }
+    if state <= 1u {
+      let c = cell / grid.size;
+      let alive = coloring.alive;
+      return mix(mix(alive[0], alive[1], c.x), mix(alive[2], alive[3], c.x), c.y);
+    }
+    // Decaying states of Generations rules go along their gradient as they
+    // approach death.
+    return gradientColor(DYING_COLORS, progress);
+}
+
 @fragment
 //output location is color attachment 0
 fn fragment_main(input: FragInput) -> @location(0) vec4f {
-    let c = input.cell / grid;
-    return vec4f(c, 1 - c.x, 1);
+    return cellColor(input.cell, input.index, input.state);
+}
+
+struct FullscreenOutput {
+  @builtin(position) pos: vec4f,
+  // The position in clip space, from -1 to 1 across the window.
+  @location(0) clip: vec2f,
+};
+
+// A triangle covering the whole window, with no vertex buffer.
+@vertex
+fn fullscreen_vertex(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+  let clip = vec2f(f32(index % 2u) * 4 - 1, f32(index / 2u) * 4 - 1);
+  var output: FullscreenOutput;
+  output.pos = vec4f(clip, 0, 1);
+  output.clip = clip;
+  return output;
+}
+
+// The color of the cell containing the point `gridPos`, with an alpha of 0
+// where no cell is drawn, so that the background shows through.
+fn colorAt(gridPos: vec2f) -> vec4f {
+  let cell = floor(gridPos);
+  if any(cell < vec2f(0)) || any(cell >= grid.size) {
+    return vec4f(0);
+  }
+  let index = u32(cell.y) * u32(grid.size.x) + u32(cell.x);
+  let state = stateAt(index);
+  if !isVisible(index, state) {
+    return vec4f(0);
+  }
+  return cellColor(cell, index, state);
+}
+
+// Shows the cell under every pixel, magnified without any blending. Zoomed
+// out, a pixel covers several cells; with SMOOTH up to MAX_SAMPLES of them
+// are averaged along each axis, otherwise the one in its middle is shown.
+@fragment
+fn fullscreen_fragment(input: FullscreenOutput) -> @location(0) vec4f {
+  let gridPos = camera.center + input.clip / camera.scale;
+  // The size of the pixel in cells.
+  let footprint = abs(vec2f(dpdx(gridPos.x), dpdy(gridPos.y)));
+  let samples = min(vec2u(ceil(footprint)), vec2u(MAX_SAMPLES));
+  if !SMOOTH || all(samples <= vec2u(1)) {
+    return colorAt(gridPos);
+  }
+
+  // The cells drawn are averaged among themselves, weighted by their alpha,
+  // and their share of the samples makes the alpha.
+  var sum = vec4f(0);
+  for (var y = 0u; y < samples.y; y++) {
+    for (var x = 0u; x < samples.x; x++) {
+      let offset = (vec2f(f32(x), f32(y)) + 0.5) / vec2f(samples) - 0.5;
+      let color = colorAt(gridPos + offset * footprint);
+      sum += vec4f(color.rgb * color.a, color.a);
+    }
+  }
+  if sum.a == 0 {
+    return vec4f(0);
+  }
+  return vec4f(sum.rgb / sum.a, sum.a / f32(samples.x * samples.y));
 }
\ No newline at end of file
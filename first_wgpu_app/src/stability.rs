@@ -0,0 +1,242 @@
+//! Noticing when a world has died out or settled into a cycle.
+//!
+//! A world whose rule is deterministic repeats itself forever as soon as any
+//! generation repeats, so comparing the hashes of recent generations finds
+//! still lifes (period 1) and oscillators alike. Hashes are taken on the GPU,
+//! see [`crate::population::Census`].
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    str::FromStr,
+};
+
+use crate::population::Census;
+
+/// Cycles longer than this many generations go unnoticed.
+pub const MAX_PERIOD: u64 = 1024;
+
+/// What to do once the world is found to have died out or settled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnStable {
+    /// Only report it.
+    #[default]
+    Log,
+    /// Pause the window, or stop a headless run early.
+    Pause,
+    /// Start again from a new soup, with the next seed.
+    Reseed,
+}
+
+impl OnStable {
+    pub const ALL: [OnStable; 3] = [OnStable::Log, OnStable::Pause, OnStable::Reseed];
+
+    fn name(&self) -> &'static str {
+        match self {
+            OnStable::Log => "log",
+            OnStable::Pause => "pause",
+            OnStable::Reseed => "reseed",
+        }
+    }
+}
+
+impl FromStr for OnStable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OnStable::ALL
+            .into_iter()
+            .find(|action| action.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<&str> = OnStable::ALL.iter().map(OnStable::name).collect();
+                format!("unknown action {s:?}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for OnStable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every cell is dead.
+    Extinct,
+    /// The world repeats every `period` generations, 1 for a still life.
+    Periodic { period: u64 },
+}
+
+/// When and how a world settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stabilization {
+    /// The first generation of the final state or cycle, as
+    /// [`crate::world::World::generation`] counts them.
+    pub generation: u64,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Stabilization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.outcome {
+            Outcome::Extinct => write!(f, "died out at generation {}", self.generation),
+            Outcome::Periodic { period } => {
+                write!(f, "stabilized at generation {} with period {period}", self.generation)
+            }
+        }
+    }
+}
+
+/// Watches the censuses of successive generations for a repeat.
+pub struct StabilityDetector {
+    /// The generation the run started or restarted at.
+    start: u64,
+    /// The last generation observed.
+    last: Option<u64>,
+    /// The generation of each recent hash, and the recent hashes in order.
+    seen: HashMap<u64, u64>,
+    recent: VecDeque<u64>,
+    /// Set once the world has settled, which is only reported once.
+    settled: bool,
+}
+
+impl StabilityDetector {
+    /// Starts watching a run whose first generation is `start`.
+    pub fn new(start: u64) -> Self {
+        StabilityDetector {
+            start,
+            last: None,
+            seen: HashMap::new(),
+            recent: VecDeque::new(),
+            settled: false,
+        }
+    }
+
+    /// Forgets everything observed so far, e.g. after a reseed, and watches
+    /// again from generation `start`.
+    pub fn restart(&mut self, start: u64) {
+        *self = StabilityDetector::new(start);
+    }
+
+    /// Whether the world has settled since the last restart.
+    pub fn is_settled(&self) -> bool {
+        self.settled
+    }
+
+    /// Takes in the census of the next generation, and returns how the world
+    /// settled the first time it is found to.
+    ///
+    /// Censuses must come in the order of their generations. A generation
+    /// observed again means it was edited, which restarts the detector from it.
+    /// Censuses from before the start are left out.
+    pub fn observe(&mut self, census: &Census) -> Option<Stabilization> {
+        if census.generation < self.start {
+            return None;
+        }
+        match self.last {
+            Some(last) if census.generation <= last => self.restart(census.generation),
+            // A generation was skipped, so a repeat could be missed. Starting
+            // over keeps the reported generation and period exact.
+            Some(last) if census.generation > last + 1 => {
+                self.seen.clear();
+                self.recent.clear();
+            }
+            _ => (),
+        }
+        self.last = Some(census.generation);
+        if self.settled {
+            return None;
+        }
+
+        let stabilization = if census.population == 0 {
+            Some(Stabilization {
+                generation: census.generation,
+                outcome: Outcome::Extinct,
+            })
+        } else {
+            self.seen.get(&census.hash).map(|&first| Stabilization {
+                generation: first,
+                outcome: Outcome::Periodic {
+                    period: census.generation - first,
+                },
+            })
+        };
+        if stabilization.is_some() {
+            self.settled = true;
+            return stabilization;
+        }
+
+        self.seen.insert(census.hash, census.generation);
+        self.recent.push_back(census.hash);
+        if self.recent.len() as u64 > MAX_PERIOD {
+            let hash = self.recent.pop_front().unwrap();
+            self.seen.remove(&hash);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds generations `start..` with the given hashes, all alive.
+    fn observe_all(detector: &mut StabilityDetector, start: u64, hashes: &[u64]) -> Vec<Stabilization> {
+        hashes
+            .iter()
+            .zip(start..)
+            .filter_map(|(&hash, generation)| {
+                detector.observe(&Census { generation, population: 1, hash })
+            })
+            .collect()
+    }
+
+    fn periodic(generation: u64, period: u64) -> Stabilization {
+        Stabilization { generation, outcome: Outcome::Periodic { period } }
+    }
+
+    #[test]
+    fn reports_the_start_and_period_of_the_first_cycle_once() {
+        let mut detector = StabilityDetector::new(0);
+        assert_eq!(observe_all(&mut detector, 0, &[1, 2, 3, 4, 5, 3, 4, 5, 3]), [periodic(2, 3)]);
+        assert!(detector.is_settled());
+
+        let mut detector = StabilityDetector::new(0);
+        assert_eq!(observe_all(&mut detector, 0, &[7, 8, 8, 8]), [periodic(1, 1)]);
+    }
+
+    #[test]
+    fn reports_extinction() {
+        let mut detector = StabilityDetector::new(0);
+        detector.observe(&Census { generation: 0, population: 3, hash: 1 });
+        let census = Census { generation: 1, population: 0, hash: 0 };
+        assert_eq!(
+            detector.observe(&census),
+            Some(Stabilization { generation: 1, outcome: Outcome::Extinct })
+        );
+        assert_eq!(Stabilization { generation: 1, outcome: Outcome::Extinct }.to_string(), "died out at generation 1");
+    }
+
+    #[test]
+    fn restarts_after_a_reseed_or_an_edit() {
+        let mut detector = StabilityDetector::new(0);
+        assert_eq!(observe_all(&mut detector, 0, &[1, 2, 1]), [periodic(0, 2)]);
+
+        // Censuses of the old run are left out.
+        detector.restart(10);
+        assert_eq!(observe_all(&mut detector, 8, &[1, 2, 5, 6, 5]), [periodic(10, 2)]);
+
+        // Generation 3 edited while paused.
+        let mut detector = StabilityDetector::new(0);
+        assert_eq!(observe_all(&mut detector, 0, &[1, 2, 3, 4]), []);
+        assert_eq!(observe_all(&mut detector, 3, &[2, 5, 2]), [periodic(3, 2)]);
+    }
+
+    #[test]
+    fn forgets_hashes_older_than_the_longest_period() {
+        let mut detector = StabilityDetector::new(0);
+        let hashes: Vec<u64> = (0..=MAX_PERIOD).chain([0]).collect();
+        assert_eq!(observe_all(&mut detector, 0, &hashes), []);
+        assert_eq!(observe_all(&mut detector, MAX_PERIOD + 2, &[MAX_PERIOD]), [periodic(MAX_PERIOD, 2)]);
+    }
+}
@@ -0,0 +1,551 @@
+//! Table-driven cellular automata in Golly's `.rule` / `@TABLE` format.
+//!
+//! A rule table lists transitions such as `3,1,a,a,a,a,a,a,a,1`: the state of
+//! the center cell, the states of its neighbors and the state the center cell
+//! moves to. Entries can be literal states or variables (`var a={0,2,3}`);
+//! a variable used more than once in a transition is bound, i.e. it takes the
+//! same value everywhere in that transition. Cells that match no transition
+//! keep their state.
+//!
+//! For the GPU, transitions are expanded (bound variables, then symmetries)
+//! into a flat list of rules. For every neighborhood position and state we
+//! store a bitset over that list marking the rules which accept that state
+//! at that position. The compute shader ANDs the bitsets of a cell's
+//! neighborhood together and the lowest set bit is the first matching rule.
+use std::{collections::HashMap, fmt, fs, io, path::Path};
+
+/// Golly's WireWorld rule, available without a file as `wireworld`.
+const WIREWORLD: &str = include_str!("../rules/WireWorld.rule");
+
+/// Golly rule tables support at most this many states.
+pub use crate::rule::MAX_STATES;
+
+/// Upper bound on the size of the uploaded table, in `u32` words, so that it fits
+/// in the default `max_storage_buffer_binding_size` of 128 MiB.
+const MAX_TABLE_WORDS: usize = (128 << 20) / 4;
+
+/// A set of states, one bit per state.
+type StateSet = [u64; 4];
+
+fn single(state: u32) -> StateSet {
+    let mut set = [0; 4];
+    set[state as usize / 64] |= 1 << (state % 64);
+    set
+}
+
+fn states_in(set: &StateSet) -> impl Iterator<Item = u32> + '_ {
+    (0..MAX_STATES).filter(|&s| set[s as usize / 64] & (1 << (s % 64)) != 0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// The center cell and its 8 surrounding cells, in the order C,N,NE,E,SE,S,SW,W,NW.
+    Moore,
+    /// The center cell and its 4 orthogonal neighbors, in the order C,N,E,S,W.
+    VonNeumann,
+}
+
+impl Neighborhood {
+    /// Number of inputs of a transition, including the center cell.
+    pub fn inputs(&self) -> usize {
+        match self {
+            Neighborhood::Moore => 9,
+            Neighborhood::VonNeumann => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Symmetry {
+    None,
+    Rotate4,
+    Rotate8,
+    ReflectHorizontal,
+    Rotate4Reflect,
+    Rotate8Reflect,
+    Permute,
+}
+
+impl Symmetry {
+    /// The permutations of the neighbor ring (the inputs after the center
+    /// cell) that map a transition onto an equivalent one. `Permute` is
+    /// handled separately as it would produce up to 8! permutations.
+    fn permutations(&self, neighborhood: Neighborhood) -> Result<Vec<Vec<usize>>, String> {
+        let n = neighborhood.inputs() - 1;
+        // One step of rotation moves a neighbor by 45 degrees in the Moore
+        // neighborhood and by 90 degrees in the von Neumann one.
+        let quarter = n / 4;
+        let rotation = |k: usize| -> Vec<usize> { (0..n).map(|i| (i + k) % n).collect() };
+        let reflect = |p: Vec<usize>| -> Vec<usize> { p.iter().map(|&i| (n - i) % n).collect() };
+
+        let rotations: Vec<Vec<usize>> = match self {
+            Symmetry::None | Symmetry::ReflectHorizontal => vec![rotation(0)],
+            Symmetry::Rotate4 | Symmetry::Rotate4Reflect => {
+                (0..4).map(|k| rotation(k * quarter)).collect()
+            }
+            Symmetry::Rotate8 | Symmetry::Rotate8Reflect => {
+                if neighborhood != Neighborhood::Moore {
+                    return Err("rotate8 symmetries need the Moore neighborhood".to_string());
+                }
+                (0..8).map(rotation).collect()
+            }
+            Symmetry::Permute => unreachable!("permute has no fixed set of permutations"),
+        };
+        let reflected = matches!(
+            self,
+            Symmetry::ReflectHorizontal | Symmetry::Rotate4Reflect | Symmetry::Rotate8Reflect
+        );
+
+        let mut all = rotations.clone();
+        if reflected {
+            all.extend(rotations.into_iter().map(reflect));
+        }
+        Ok(all)
+    }
+}
+
+/// One expanded transition: a set of accepted states per input and the new state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Transition {
+    inputs: Vec<StateSet>,
+    output: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleTable {
+    pub name: String,
+    pub states: u32,
+    pub neighborhood: Neighborhood,
+    transitions: Vec<Transition>,
+}
+
+#[derive(Debug)]
+pub enum TableError {
+    Io(io::Error),
+    /// A malformed line, with its 1-based line number.
+    Syntax { line: usize, message: String },
+    /// A required header such as `n_states` is missing.
+    MissingHeader(&'static str),
+    /// The expanded table would not fit in a storage buffer.
+    TooLarge,
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::Io(e) => write!(f, "cannot read rule table: {e}"),
+            TableError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            TableError::MissingHeader(name) => write!(f, "rule table has no '{name}' line"),
+            TableError::TooLarge => write!(f, "rule table is too large to upload to the GPU"),
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+impl From<io::Error> for TableError {
+    fn from(e: io::Error) -> Self {
+        TableError::Io(e)
+    }
+}
+
+impl RuleTable {
+    /// Loads a `.rule` file (or a bare table file without `@` sections).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TableError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let fallback = path.file_stem().and_then(|s| s.to_str()).unwrap_or("table");
+        Self::parse(&text, fallback)
+    }
+
+    /// Brian Silverman's WireWorld: 0 empty, 1 electron head, 2 electron tail, 3 conductor.
+    pub fn wireworld() -> Self {
+        Self::parse(WIREWORLD, "WireWorld").expect("built-in WireWorld table is valid")
+    }
+
+    /// Parses the text of a rule file. `name` is used if the file has no `@RULE` line.
+    pub fn parse(text: &str, name: &str) -> Result<Self, TableError> {
+        let mut name = name.to_string();
+        let mut states = None;
+        let mut neighborhood = None;
+        let mut symmetry = None;
+        let mut variables: HashMap<String, StateSet> = HashMap::new();
+        let mut transitions = Vec::new();
+
+        // Files without any sections are treated as a bare table.
+        let has_sections = text.lines().any(|l| l.trim_start().starts_with('@'));
+        let mut in_table = !has_sections;
+
+        for (index, raw) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let syntax = |message: String| TableError::Syntax { line: line_no, message };
+            let line = raw.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(section) = line.strip_prefix('@') {
+                let mut words = section.split_whitespace();
+                let section = words.next().unwrap_or("");
+                if section == "RULE" {
+                    if let Some(rule_name) = words.next() {
+                        name = rule_name.to_string();
+                    }
+                }
+                in_table = section == "TABLE";
+                continue;
+            }
+            if !in_table {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim();
+                match key.trim() {
+                    "n_states" => match value.parse() {
+                        Ok(n) if (2..=MAX_STATES).contains(&n) => states = Some(n),
+                        _ => return Err(syntax(format!("invalid n_states {value:?}"))),
+                    },
+                    "neighborhood" => {
+                        neighborhood = Some(match value.to_ascii_lowercase().as_str() {
+                            "moore" => Neighborhood::Moore,
+                            "vonneumann" => Neighborhood::VonNeumann,
+                            _ => return Err(syntax(format!("unsupported neighborhood {value:?}"))),
+                        })
+                    }
+                    "symmetries" => {
+                        symmetry = Some(match value {
+                            "none" => Symmetry::None,
+                            "rotate4" => Symmetry::Rotate4,
+                            "rotate8" => Symmetry::Rotate8,
+                            "reflect_horizontal" => Symmetry::ReflectHorizontal,
+                            "rotate4reflect" => Symmetry::Rotate4Reflect,
+                            "rotate8reflect" => Symmetry::Rotate8Reflect,
+                            "permute" => Symmetry::Permute,
+                            _ => return Err(syntax(format!("unsupported symmetries {value:?}"))),
+                        })
+                    }
+                    other => return Err(syntax(format!("unknown setting {other:?}"))),
+                }
+                continue;
+            }
+
+            let states = states.ok_or(TableError::MissingHeader("n_states"))?;
+            let neighborhood = neighborhood.ok_or(TableError::MissingHeader("neighborhood"))?;
+            let symmetry = symmetry.ok_or(TableError::MissingHeader("symmetries"))?;
+
+            // Resolves a literal state or a variable name to a set of states.
+            let resolve = |token: &str, variables: &HashMap<String, StateSet>| {
+                if let Ok(state) = token.parse::<u32>() {
+                    if state >= states {
+                        return Err(syntax(format!("state {state} is not below n_states {states}")));
+                    }
+                    Ok(single(state))
+                } else {
+                    variables
+                        .get(token)
+                        .copied()
+                        .ok_or_else(|| syntax(format!("unknown variable {token:?}")))
+                }
+            };
+
+            if let Some(definition) = line.strip_prefix("var ") {
+                let (var, values) = definition
+                    .split_once('=')
+                    .ok_or_else(|| syntax("expected 'var name={...}'".to_string()))?;
+                let values = values
+                    .trim()
+                    .strip_prefix('{')
+                    .and_then(|v| v.strip_suffix('}'))
+                    .ok_or_else(|| syntax("variable values must be enclosed in {}".to_string()))?;
+                let mut set = [0; 4];
+                for token in values.split(',').map(str::trim) {
+                    let other = resolve(token, &variables)?;
+                    set.iter_mut().zip(other).for_each(|(a, b)| *a |= b);
+                }
+                variables.insert(var.trim().to_string(), set);
+                continue;
+            }
+
+            // Transitions are comma separated, or plain digits if every state is below 10.
+            let tokens: Vec<&str> = if line.contains(',') {
+                line.split(',').map(str::trim).collect()
+            } else if line.chars().all(|c| c.is_ascii_digit()) {
+                (0..line.len()).map(|i| &line[i..i + 1]).collect()
+            } else {
+                line.split_whitespace().collect()
+            };
+            let inputs = neighborhood.inputs();
+            if tokens.len() != inputs + 1 {
+                return Err(syntax(format!(
+                    "expected {} entries in transition, found {}",
+                    inputs + 1,
+                    tokens.len()
+                )));
+            }
+
+            // Variables that occur more than once are bound: expand every
+            // combination of their values into a separate transition.
+            let mut bound: Vec<&str> = Vec::new();
+            for token in &tokens {
+                let repeated = tokens.iter().filter(|t| *t == token).count() > 1;
+                if variables.contains_key(*token) && repeated && !bound.contains(token) {
+                    bound.push(token);
+                }
+            }
+            let output = tokens[inputs];
+            if variables.contains_key(output) && !bound.contains(&output) {
+                return Err(syntax(format!("output variable {output:?} is not bound to an input")));
+            }
+
+            let choices: Vec<Vec<u32>> = bound
+                .iter()
+                .map(|var| states_in(&variables[*var]).collect())
+                .collect();
+            let mut assignment = vec![0; bound.len()];
+            loop {
+                let value_of = |token: &str| -> Result<StateSet, TableError> {
+                    match bound.iter().position(|var| *var == token) {
+                        Some(k) => Ok(single(choices[k][assignment[k]])),
+                        None => resolve(token, &variables),
+                    }
+                };
+                let inputs = tokens[..inputs]
+                    .iter()
+                    .map(|token| value_of(token))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let output = states_in(&value_of(output)?).next().unwrap();
+                expand_symmetries(&mut transitions, Transition { inputs, output }, symmetry, neighborhood)
+                    .map_err(syntax)?;
+                if transitions.len() > MAX_TABLE_WORDS {
+                    return Err(TableError::TooLarge);
+                }
+
+                // Advance to the next combination of bound values, odometer style.
+                let mut k = 0;
+                while k < bound.len() {
+                    assignment[k] += 1;
+                    if assignment[k] < choices[k].len() {
+                        break;
+                    }
+                    assignment[k] = 0;
+                    k += 1;
+                }
+                if k == bound.len() {
+                    break;
+                }
+            }
+        }
+
+        let table = RuleTable {
+            name,
+            states: states.ok_or(TableError::MissingHeader("n_states"))?,
+            neighborhood: neighborhood.ok_or(TableError::MissingHeader("neighborhood"))?,
+            transitions,
+        };
+        if table.buffer_len() > MAX_TABLE_WORDS {
+            return Err(TableError::TooLarge);
+        }
+        Ok(table)
+    }
+
+    /// Number of `u32` words needed for one bitset over all rules.
+    fn words(&self) -> usize {
+        self.transitions.len().div_ceil(32).max(1)
+    }
+
+    /// The state a cell moves to, given the states of its neighborhood in
+    /// transition order, center first. Cells matching no rule keep their state.
+    pub fn next_state(&self, neighborhood: &[u32]) -> u32 {
+        let accepts = |set: &StateSet, state: u32| set[state as usize / 64] & (1 << (state % 64)) != 0;
+        self.transitions
+            .iter()
+            .find(|t| t.inputs.iter().zip(neighborhood).all(|(set, &state)| accepts(set, state)))
+            .map_or(neighborhood[0], |t| t.output)
+    }
+
+    fn buffer_len(&self) -> usize {
+        let lookup = self.neighborhood.inputs() * self.states as usize * self.words();
+        2 + lookup + self.words() * 32
+    }
+
+    /// The contents of the table storage buffer, laid out like `Table` in `c_shader.wgsl`:
+    /// the number of bitset words, the number of inputs, the bitsets indexed by
+    /// `(input * states + state) * words + word`, and finally the output state of each rule.
+    pub fn buffer_data(&self) -> Vec<u32> {
+        let inputs = self.neighborhood.inputs();
+        let words = self.words();
+        let mut data = vec![0; self.buffer_len()];
+        data[0] = words as u32;
+        data[1] = inputs as u32;
+
+        let (lookup, outputs) = data[2..].split_at_mut(inputs * self.states as usize * words);
+        for (r, transition) in self.transitions.iter().enumerate() {
+            for (input, set) in transition.inputs.iter().enumerate() {
+                for state in states_in(set) {
+                    lookup[(input * self.states as usize + state as usize) * words + r / 32] |=
+                        1 << (r % 32);
+                }
+            }
+            outputs[r] = transition.output;
+        }
+        data
+    }
+}
+
+/// Appends `transition` and all of its distinct symmetric variants to `transitions`.
+fn expand_symmetries(
+    transitions: &mut Vec<Transition>,
+    transition: Transition,
+    symmetry: Symmetry,
+    neighborhood: Neighborhood,
+) -> Result<(), String> {
+    let center = transition.inputs[0];
+    let ring = &transition.inputs[1..];
+    let mut variants: Vec<Vec<StateSet>> = Vec::new();
+
+    if symmetry == Symmetry::Permute {
+        // Every arrangement of the neighbors: walk the distinct permutations of
+        // the sorted multiset, which stays small when many inputs are equal.
+        let mut ring = ring.to_vec();
+        ring.sort();
+        loop {
+            variants.push(ring.clone());
+            if !next_permutation(&mut ring) {
+                break;
+            }
+        }
+    } else {
+        for permutation in symmetry.permutations(neighborhood)? {
+            let variant: Vec<StateSet> = permutation.iter().map(|&i| ring[i]).collect();
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+    }
+
+    for variant in variants {
+        let mut inputs = Vec::with_capacity(variant.len() + 1);
+        inputs.push(center);
+        inputs.extend(variant);
+        transitions.push(Transition {
+            inputs,
+            output: transition.output,
+        });
+    }
+    Ok(())
+}
+
+/// Rearranges `items` into the next lexicographically greater permutation.
+/// Returns `false` once the last permutation has been reached.
+fn next_permutation<T: Ord>(items: &mut [T]) -> bool {
+    let Some(i) = items.windows(2).rposition(|w| w[0] < w[1]) else {
+        return false;
+    };
+    let j = items.iter().rposition(|x| *x > items[i]).unwrap();
+    items.swap(i, j);
+    items[i + 1..].reverse();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(body: &str) -> RuleTable {
+        RuleTable::parse(body, "test").unwrap_or_else(|e| panic!("cannot parse table: {e}"))
+    }
+
+    fn syntax_error_line(text: &str) -> usize {
+        match RuleTable::parse(text, "test") {
+            Err(TableError::Syntax { line, .. }) => line,
+            other => panic!("expected a syntax error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn wireworld_moves_electrons_along_conductors() {
+        let wireworld = RuleTable::wireworld();
+        assert_eq!((wireworld.name.as_str(), wireworld.states), ("WireWorld", 4));
+        // Center first, then N, NE, E, SE, S, SW, W, NW.
+        assert_eq!(wireworld.next_state(&[1, 3, 3, 0, 0, 0, 0, 0, 2]), 2);
+        assert_eq!(wireworld.next_state(&[2, 1, 1, 1, 0, 0, 0, 0, 0]), 3);
+        assert_eq!(wireworld.next_state(&[3, 0, 0, 0, 0, 1, 0, 0, 0]), 1);
+        assert_eq!(wireworld.next_state(&[3, 1, 0, 0, 0, 0, 2, 0, 1]), 1);
+        // No transition matches three heads, or an empty cell.
+        assert_eq!(wireworld.next_state(&[3, 1, 0, 1, 0, 1, 0, 0, 0]), 3);
+        assert_eq!(wireworld.next_state(&[0, 1, 1, 1, 1, 1, 1, 1, 1]), 0);
+    }
+
+    #[test]
+    fn symmetries_expand_to_every_variant() {
+        let header = |symmetries: &str, neighborhood: &str| {
+            format!("n_states:3\nneighborhood:{neighborhood}\nsymmetries:{symmetries}\n")
+        };
+        // A cell is born next to a 1 to its north, and under rotate4 to any side.
+        let rotated = table(&(header("rotate4", "vonNeumann") + "0,1,0,0,0,1"));
+        assert_eq!(rotated.transitions.len(), 4);
+        for side in 1..5 {
+            let mut cells = [0; 5];
+            cells[side] = 1;
+            assert_eq!(rotated.next_state(&cells), 1, "neighbor on side {side}");
+        }
+        assert_eq!(table(&(header("none", "vonNeumann") + "0,1,0,0,0,1")).next_state(&[0, 0, 1, 0, 0]), 0);
+
+        // Reflection mirrors east and west, keeping north.
+        let reflected = table(&(header("reflect_horizontal", "Moore") + "0,1,2,0,0,0,0,0,0,1"));
+        assert_eq!(reflected.transitions.len(), 2);
+        assert_eq!(reflected.next_state(&[0, 1, 0, 0, 0, 0, 0, 0, 2]), 1);
+        assert_eq!(reflected.next_state(&[0, 2, 1, 0, 0, 0, 0, 0, 0]), 0);
+
+        // Variants equal to another one are only kept once.
+        assert_eq!(table(&(header("rotate8reflect", "Moore") + "0,1,0,0,0,0,0,0,0,1")).transitions.len(), 8);
+        assert_eq!(table(&(header("rotate8", "Moore") + "0,1,1,1,1,1,1,1,1,1")).transitions.len(), 1);
+        // Every distinct arrangement of two 1s and a 2 among 8 neighbors.
+        assert_eq!(table(&(header("permute", "Moore") + "0,1,1,2,0,0,0,0,0,1")).transitions.len(), 8 * 7 * 6 / 2);
+    }
+
+    #[test]
+    fn bound_variables_take_the_same_value() {
+        let text = "n_states:3\nneighborhood:vonNeumann\nsymmetries:none\nvar a={1,2}\nvar b={1,2}\n0,a,a,b,0,a\n";
+        let bound = table(text);
+        // `a` is expanded into one transition per value, `b` is used once and stays a set.
+        assert_eq!(bound.transitions.len(), 2);
+        assert_eq!(bound.next_state(&[0, 2, 2, 1, 0]), 2);
+        assert_eq!(bound.next_state(&[0, 1, 1, 2, 0]), 1);
+        assert_eq!(bound.next_state(&[0, 1, 2, 1, 0]), 0);
+    }
+
+    #[test]
+    fn bare_tables_need_no_sections() {
+        let bare = table("# Comments are fine.\nn_states:2\nneighborhood:vonNeumann\nsymmetries:none\n011111\n");
+        assert_eq!(bare.name, "test");
+        assert_eq!(bare.next_state(&[0, 1, 1, 1, 1]), 1);
+        // With sections, only @TABLE is read and @RULE names the table.
+        let named = table("@RULE Named\nanything: here\n@TABLE\nn_states:2\nneighborhood:Moore\nsymmetries:none\n");
+        assert_eq!(named.name, "Named");
+    }
+
+    #[test]
+    fn reports_malformed_tables() {
+        let header = "n_states:3\nneighborhood:vonNeumann\nsymmetries:rotate4\n";
+        assert_eq!(syntax_error_line("n_states:1"), 1);
+        assert_eq!(syntax_error_line("n_states:3\nneighborhood:hex"), 2);
+        assert_eq!(syntax_error_line("n_states:3\nsymmetries:rotate2"), 2);
+        assert_eq!(syntax_error_line("n_states:3\ncolors:none"), 2);
+        assert_eq!(syntax_error_line(&format!("{header}0,1,0,0,0")), 4);
+        assert_eq!(syntax_error_line(&format!("{header}0,3,0,0,0,1")), 4);
+        assert_eq!(syntax_error_line(&format!("{header}0,x,0,0,0,1")), 4);
+        assert_eq!(syntax_error_line(&format!("{header}var a={{1,2}}\nvar b={{1,2}}\n0,a,0,0,0,b")), 6);
+        assert_eq!(syntax_error_line(&format!("{header}var a=1,2")), 4);
+        assert_eq!(syntax_error_line("n_states:3\nneighborhood:vonNeumann\nsymmetries:rotate8\n0,1,0,0,0,1"), 4);
+        assert!(matches!(
+            RuleTable::parse("neighborhood:Moore\nsymmetries:none\n0,1,0,0,0,0,0,0,0,1", "test"),
+            Err(TableError::MissingHeader("n_states"))
+        ));
+        assert!(matches!(
+            RuleTable::parse("n_states:2\nsymmetries:none", "test"),
+            Err(TableError::MissingHeader("neighborhood"))
+        ));
+    }
+}
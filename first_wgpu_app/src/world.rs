@@ -0,0 +1,1186 @@
+//! The simulated grid of cells and the GPU resources that step and draw it.
+use std::{borrow::Cow, mem, ops::{Range, RangeInclusive}};
+
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    boundary::Boundary,
+    camera::Camera,
+    cells,
+    cli::{Options, Renderer, MAX_GRID_SIZE, MAX_QUAD_CELLS},
+    coloring::{CellActivity, ColorMode},
+    palette::Palette,
+    seed,
+};
+
+/// Picks an adapter (able to present to `surface`, if given) and creates a device on it.
+/// With `force_fallback_adapter`, only software adapters such as lavapipe are considered.
+pub async fn request_device(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface<'_>>,
+    force_fallback_adapter: bool,
+) -> (wgpu::Adapter, wgpu::Device, wgpu::Queue) {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: surface,
+            force_fallback_adapter,
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to find a suitable adapter");
+    // Ask for everything the adapter offers, so that large grids fit in a single buffer.
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                required_limits: adapter.limits(),
+                ..Default::default()
+            },
+            None, // Trace path
+        )
+        .await
+        .unwrap();
+    (adapter, device, queue)
+}
+
+/// The contents of the grid uniform, laid out like `Grid` in the shaders: the
+/// size as two floats, the boundary mode, and whether cells are packed.
+fn grid_uniform_data((width, height): (u32, u32), boundary: Boundary, packed: bool) -> [u32; 4] {
+    [
+        (width as f32).to_bits(),
+        (height as f32).to_bits(),
+        boundary.shader_value(),
+        packed as u32,
+    ]
+}
+
+/// The size of a cell buffer for a `width` by `height` grid, if the device
+/// can bind one that large.
+fn cell_buffer_size(device: &wgpu::Device, (width, height): (u32, u32), packed: bool) -> Result<u64, String> {
+    let cells = u64::from(width) * u64::from(height);
+    let buffer_size = if packed { cells / 32 } else { cells } * mem::size_of::<u32>() as u64;
+    let limits = device.limits();
+    let max_size = u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size);
+    if buffer_size > max_size {
+        return Err(format!(
+            "a {width}x{height} grid needs {buffer_size} bytes per cell buffer, \
+            but this device allows only {max_size}; try --packed or a smaller grid"
+        ));
+    }
+    Ok(buffer_size)
+}
+
+/// Checks that `device` can hold a world made with `options`, which
+/// [`World::new`] assumes.
+pub fn check_limits(device: &wgpu::Device, options: &Options) -> Result<(), String> {
+    cell_buffer_size(device, options.grid_size, options.packed).map(|_| ())
+}
+
+/// The ping-pong pair of cell buffers.
+fn create_cell_buffers(device: &wgpu::Device, size: u64) -> [wgpu::Buffer; 2] {
+    ["Cell state A", "Cell state B"].map(|label| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    })
+}
+
+/// The most memory the generations kept for rewinding may take together.
+/// Each is a copy of the whole grid, so large grids keep fewer of them.
+const HISTORY_BUDGET: u64 = 256 * 1024 * 1024;
+
+/// The buffers holding the generations kept for rewinding: `generations` of
+/// them, or as many as fit in [`HISTORY_BUDGET`].
+fn create_history(device: &wgpu::Device, generations: u32, size: u64) -> Vec<wgpu::Buffer> {
+    let fitting = u32::try_from(HISTORY_BUDGET / size).unwrap_or(u32::MAX);
+    if generations > fitting {
+        log::warn!("Cannot keep {generations} generations of a grid this large for rewinding, keeping {fitting}");
+    }
+    (0..generations.min(fitting))
+        .map(|_| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Cell state history"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        })
+        .collect()
+}
+
+/// One bind group for each direction of the ping-pong: bind group `i` reads
+/// `cells[i]` and writes the other one. `others` are the grid uniform, the
+/// rule uniform, the rule table, the census, the camera uniform and the
+/// coloring uniform.
+fn create_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    others: &[&wgpu::Buffer; 6],
+    cells: &[wgpu::Buffer; 2],
+) -> Vec<wgpu::BindGroup> {
+    let [uniform_buf, rule_buf, table_buf, census_buf, camera_buf, coloring_buf] = others;
+    [("Cell renderer bind group A", 0), ("Cell renderer bind group B", 1)]
+        .into_iter()
+        .map(|(label, input)| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: cells[input].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: cells[1 - input].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: rule_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: table_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: census_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: camera_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: coloring_buf.as_entire_binding(),
+                    },
+                ],
+            })
+        })
+        .collect()
+}
+
+/// Copies `buffer` back from the GPU.
+///
+/// This waits for the GPU to finish all submitted work.
+fn download(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> Vec<u32> {
+    download_range(device, queue, buffer, 0..buffer.size())
+}
+
+/// Copies the bytes `range` of `buffer` back from the GPU, which must start
+/// and end on a word.
+///
+/// This waits for the GPU to finish all submitted work.
+fn download_range(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer, range: Range<u64>) -> Vec<u32> {
+    // Buffers that the CPU can map must not be used as storage, so copy into one first.
+    let size = range.end - range.start;
+    let download_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Download"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(buffer, range.start, &download_buffer, 0, size);
+    queue.submit([encoder.finish()]);
+
+    // Mapping only completes once the copy has finished, which polling waits for.
+    let buffer_slice = download_buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .expect("Mapping callback was dropped")
+        .expect("Failed to map the download buffer");
+    let words = bytemuck::cast_slice(&buffer_slice.get_mapped_range()).to_vec();
+    words
+}
+
+/// Records a compute pass running `pipeline` once for every cell.
+fn dispatch_cells(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::ComputePipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    (width, height): (u32, u32),
+) {
+    let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+    compute_pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        compute_pass.set_bind_group(index as u32, *bind_group, &[]);
+    }
+    compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+}
+
+/// The pipeline drawing the cells with `renderer`. The quads only cover the
+/// cells drawn, while the fullscreen triangle covers everything. Both blend
+/// with the background, where there are no cells or where they fade out.
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    cell_shader_module: &wgpu::ShaderModule,
+    bind_group_layouts: [&wgpu::BindGroupLayout; 2],
+    surface_format: wgpu::TextureFormat,
+    renderer: Renderer,
+    smooth: bool,
+) -> wgpu::RenderPipeline {
+    let vertex_buffer_layout = wgpu::VertexBufferLayout {
+        array_stride: 2 * std::mem::size_of::<f32>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x2,
+            },
+        ],
+    };
+    let (vertex_entry_point, fragment_entry_point, vertex_buffers) = match renderer {
+        Renderer::Quads => ("vertex_main", "fragment_main", &[vertex_buffer_layout][..]),
+        Renderer::Fullscreen => ("fullscreen_vertex", "fullscreen_fragment", &[][..]),
+    };
+    let constants = [("SMOOTH".to_string(), f64::from(u8::from(smooth)))].into();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Cell pipeline"),
+        layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cell render pipeline layout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        })),
+        vertex: wgpu::VertexState {
+            module: cell_shader_module,
+            entry_point: Some(vertex_entry_point),
+            buffers: vertex_buffers,
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: cell_shader_module,
+            entry_point: Some(fragment_entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                ..Default::default()
+            },
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// The activity buffer of a world that is drawn, see [`crate::coloring`]. It
+/// is bound in a group of its own, read-only for drawing and writable for
+/// `track_cells` and `recount_neighbors`.
+struct Activity {
+    buffer: wgpu::Buffer,
+    /// Whether `buffer` holds a word for every cell. It only does while the
+    /// color mode shows it, and a placeholder stands in otherwise. A grid too
+    /// large for a buffer to hold that many cannot be colored by anything but
+    /// position.
+    tracked: bool,
+    render_layout: wgpu::BindGroupLayout,
+    track_layout: wgpu::BindGroupLayout,
+    render_bind_group: wgpu::BindGroup,
+    track_bind_group: wgpu::BindGroup,
+    track_pipeline: wgpu::ComputePipeline,
+    recount_pipeline: wgpu::ComputePipeline,
+    /// Set when the current generation was edited or replaced since its
+    /// neighbors were counted.
+    stale: bool,
+}
+
+impl Activity {
+    fn new(
+        device: &wgpu::Device,
+        simulation_shader_module: &wgpu::ShaderModule,
+        cell_layout: &wgpu::BindGroupLayout,
+        grid_size: (u32, u32),
+        tracked: bool,
+    ) -> Self {
+        let layout = |label, visibility, read_only| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            })
+        };
+        let render_layout = layout("Activity render layout", wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT, true);
+        let track_layout = layout("Activity tracking layout", wgpu::ShaderStages::COMPUTE, false);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Activity tracking pipeline layout"),
+            bind_group_layouts: &[cell_layout, &track_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: simulation_shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+        let track_pipeline = pipeline("track_cells");
+        let recount_pipeline = pipeline("recount_neighbors");
+
+        let (buffer, tracked, render_bind_group, track_bind_group) =
+            Activity::create_buffer(device, &render_layout, &track_layout, grid_size, tracked);
+        Activity {
+            buffer,
+            tracked,
+            render_layout,
+            track_layout,
+            render_bind_group,
+            track_bind_group,
+            track_pipeline,
+            recount_pipeline,
+            stale: true,
+        }
+    }
+
+    /// The size of the activity buffer of a `width` by `height` grid.
+    fn size((width, height): (u32, u32)) -> u64 {
+        u64::from(width) * u64::from(height) * mem::size_of::<u32>() as u64
+    }
+
+    /// Whether `device` can create and bind the activity buffer of a
+    /// `grid_size` grid.
+    fn fits(device: &wgpu::Device, grid_size: (u32, u32)) -> bool {
+        let limits = device.limits();
+        Activity::size(grid_size) <= u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size)
+    }
+
+    /// A zeroed activity buffer for a `grid_size` grid if it is to be
+    /// `tracked` and the device can bind one that large, or a placeholder,
+    /// and its bind groups.
+    fn create_buffer(
+        device: &wgpu::Device,
+        render_layout: &wgpu::BindGroupLayout,
+        track_layout: &wgpu::BindGroupLayout,
+        grid_size: (u32, u32),
+        tracked: bool,
+    ) -> (wgpu::Buffer, bool, wgpu::BindGroup, wgpu::BindGroup) {
+        let size = Activity::size(grid_size);
+        let tracked = tracked && Activity::fits(device, grid_size);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cell activity"),
+            size: if tracked { size } else { mem::size_of::<u32>() as u64 },
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = |label, layout| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            })
+        };
+        let render_bind_group = bind_group("Activity render bind group", render_layout);
+        let track_bind_group = bind_group("Activity tracking bind group", track_layout);
+        (buffer, tracked, render_bind_group, track_bind_group)
+    }
+
+    /// Starts over with every cell newborn and cold on a `grid_size` grid, or
+    /// frees the buffer if the activity is no longer `tracked`.
+    fn reset(&mut self, device: &wgpu::Device, grid_size: (u32, u32), tracked: bool) {
+        (self.buffer, self.tracked, self.render_bind_group, self.track_bind_group) =
+            Activity::create_buffer(device, &self.render_layout, &self.track_layout, grid_size, tracked);
+        self.stale = true;
+    }
+}
+
+pub struct World {
+    vertex_buf: Option<wgpu::Buffer>,
+    num_vertices: usize,
+    /// The ping-pong pair of cell state buffers.
+    storage_buffs: [wgpu::Buffer; 2],
+    /// The width and height of the grid in cells.
+    grid_size: (u32, u32),
+    packed: bool,
+    boundary: Boundary,
+    renderer: Renderer,
+    /// Number of generations simulated so far. The current generation is in
+    /// cell buffer `generation % 2`, the input of `bind_groups[generation % 2]`.
+    generation: u64,
+    bind_groups: Vec<wgpu::BindGroup>,
+    render_pipeline: Option<wgpu::RenderPipeline>,
+    /// What the render pipeline is made from, kept to switch renderers when
+    /// the grid grows too large for quads.
+    cell_shader_module: wgpu::ShaderModule,
+    surface_format: Option<wgpu::TextureFormat>,
+    compute_pipeline: Option<wgpu::ComputePipeline>,
+    census_pipeline: wgpu::ComputePipeline,
+    census_buf: wgpu::Buffer,
+    camera_buf: wgpu::Buffer,
+    coloring_buf: wgpu::Buffer,
+    color_mode: ColorMode,
+    palette: Palette,
+    /// Only worlds that are drawn keep track of the activity of their cells.
+    activity: Option<Activity>,
+    /// The buffers bound besides the cells, kept to bind them again to new
+    /// cell buffers when the grid is resized.
+    uniform_buf: wgpu::Buffer,
+    rule_buf: wgpu::Buffer,
+    table_buf: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Earlier generations kept for rewinding, generation `g` in
+    /// `history[g % history.len()]`.
+    history: Vec<wgpu::Buffer>,
+    /// The number of generations `--history` asks to keep, which a large grid
+    /// may not have room for.
+    history_wanted: u32,
+    /// The oldest and newest generation kept in `history`, if any.
+    kept: Option<(u64, u64)>,
+}
+
+impl World {
+    /// The size of a census copied by `take_census`.
+    pub const CENSUS_SIZE: u64 = 3 * mem::size_of::<u32>() as u64;
+
+    /// Without a `surface_format`, only the simulation is set up and the world cannot be drawn.
+    ///
+    /// Panics if the grid does not fit on the device, see [`check_limits`].
+    pub fn new(
+        surface_format: Option<&wgpu::TextureFormat>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        options: &Options,
+    ) -> Self {
+        let automaton = &options.automaton;
+        let grid_size = options.grid_size;
+        let packed = options.packed;
+
+        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grid uniforms"),
+            contents: bytemuck::cast_slice(&grid_uniform_data(grid_size, options.boundary, packed)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Until a window sets the camera, the grid fills a view of its own shape.
+        let view = PhysicalSize::new(grid_size.0, grid_size.1);
+        let camera_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera uniforms"),
+            contents: bytemuck::cast_slice(&Camera::fit(grid_size, view).uniform_data(view)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let rule_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Rule uniforms"),
+            contents: bytemuck::cast_slice(&automaton.uniform_data()),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let table_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Rule table"),
+            contents: bytemuck::cast_slice(&automaton.table_data()),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let vertices: &[f32] = &[
+        //   X,    Y,
+            -0.8, -0.8, // Triangle 1 (Blue)
+            0.8, -0.8,
+            0.8,  0.8,
+        
+            -0.8, -0.8, // Triangle 2 (Red)
+            0.8,  0.8,
+            -0.8,  0.8,
+        ];
+
+        let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cell vertices"),
+            size: mem::size_of_val(vertices) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&vertex_buf, 0, bytemuck::cast_slice(vertices));
+
+        // An array representing the active state of each cell, or of 32 cells per
+        // element in the packed layout.
+        let cell_state_array = seed::initial_cells(options);
+
+        let buffer_size = cell_buffer_size(device, grid_size, packed).unwrap_or_else(|e| panic!("{e}"));
+        let cell_state_storage = create_cell_buffers(device, buffer_size);
+
+        queue.write_buffer(&cell_state_storage[0], 0, bytemuck::cast_slice(&cell_state_array[..]));
+ 
+        queue.write_buffer(&cell_state_storage[1], 0, bytemuck::cast_slice(&cell_state_array[..]));
+
+        let cell_shader_module = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Cell shaders"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+            }
+        );
+
+        let simulation_shader_module = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Game of Life simulation shader"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("c_shader.wgsl"))),
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cell Bind Group Layout"),
+            entries: &[
+                // Binding 0: Uniform buffer (grid uniform)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 1: Read-only storage buffer (cell state input)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 2: Storage buffer (cell state output)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 3: Uniform buffer (rule masks and number of states)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 4: Read-only storage buffer (rule table)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 5: Storage buffer (census of the current generation)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 6: Uniform buffer (camera)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 7: Uniform buffer (color mode)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let census_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Census"),
+            size: World::CENSUS_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let mut color_mode = options.colors;
+        if color_mode.needs_activity() && !(surface_format.is_some() && Activity::fits(device, grid_size)) {
+            log::warn!("Cannot color cells by {color_mode} on a grid this large, coloring them by position");
+            color_mode = ColorMode::Position;
+        }
+        let activity = surface_format.map(|_| {
+            Activity::new(device, &simulation_shader_module, &bind_group_layout, grid_size, color_mode.needs_activity())
+        });
+        let palette = options.palettes.first().cloned().unwrap_or_default();
+        let coloring_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Coloring uniforms"),
+            contents: bytemuck::cast_slice(&palette.uniform_data(color_mode)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_groups = create_bind_groups(
+            device,
+            &bind_group_layout,
+            &[&uniform_buf, &rule_buf, &table_buf, &census_buf, &camera_buf, &coloring_buf],
+            &cell_state_storage,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cell pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        
+        let cell_pipeline = surface_format.map(|&surface_format| {
+            let activity_layout = &activity.as_ref().unwrap().render_layout;
+            create_render_pipeline(
+                device,
+                &cell_shader_module,
+                [&bind_group_layout, activity_layout],
+                surface_format,
+                options.renderer,
+                options.smooth,
+            )
+        });
+
+        let simulation_pipeline = device.create_compute_pipeline(
+            &wgpu::ComputePipelineDescriptor {
+                label: Some("Simulation pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &simulation_shader_module,
+                entry_point: Some(options.entry_point()),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            }
+        );
+
+        let census_pipeline = device.create_compute_pipeline(
+            &wgpu::ComputePipelineDescriptor {
+                label: Some("Census pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &simulation_shader_module,
+                entry_point: Some("take_census"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            }
+        );
+
+        let history = create_history(device, options.history, buffer_size);
+
+        Self {
+            vertex_buf: Some(vertex_buf),
+            num_vertices: vertices.len() / 2,
+            grid_size,
+            packed,
+            boundary: options.boundary,
+            renderer: options.renderer,
+            generation: 0,
+            storage_buffs: cell_state_storage,
+            bind_groups,
+            render_pipeline: cell_pipeline,
+            cell_shader_module,
+            surface_format: surface_format.copied(),
+            compute_pipeline: Some(simulation_pipeline),
+            census_pipeline,
+            census_buf,
+            camera_buf,
+            coloring_buf,
+            color_mode,
+            palette,
+            activity,
+            uniform_buf,
+            rule_buf,
+            table_buf,
+            bind_group_layout,
+            history,
+            history_wanted: options.history,
+            kept: None,
+        }
+    }
+
+    /// Copies the current generation back from the GPU.
+    ///
+    /// This waits for the GPU to finish all submitted work.
+    pub fn read_cells(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> cells::Snapshot {
+        let current = &self.storage_buffs[(self.generation % 2) as usize];
+        cells::Snapshot {
+            width: self.grid_size.0,
+            height: self.grid_size.1,
+            generation: self.generation,
+            packed: self.packed,
+            words: download(device, queue, current),
+        }
+    }
+
+    /// Copies the activity of every cell back from the GPU, with row 0 at the
+    /// bottom, if the world keeps track of it.
+    ///
+    /// This waits for the GPU to finish all submitted work, including
+    /// updating the neighbor counts of an edited generation.
+    pub fn read_activity(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Vec<CellActivity>> {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        self.recount_if_stale(&mut encoder);
+        queue.submit([encoder.finish()]);
+        let activity = self.activity.as_ref().filter(|activity| activity.tracked)?;
+        Some(download(device, queue, &activity.buffer).into_iter().map(CellActivity::unpack).collect())
+    }
+
+    /// How cells are colored.
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Colors cells by `mode` from the next frame on. The activity of the
+    /// cells is only kept while the mode shows it: switching to such a mode
+    /// starts every cell over as newborn and cold, and switching back to
+    /// position frees it.
+    pub fn set_color_mode(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, mode: ColorMode) -> Result<(), String> {
+        let Some(activity) = &mut self.activity else {
+            return match mode.needs_activity() {
+                true => Err("only worlds that are drawn keep track of the activity of their cells".to_string()),
+                false => Ok(()),
+            };
+        };
+        if mode.needs_activity() && !Activity::fits(device, self.grid_size) {
+            return Err(format!("cannot color cells by {mode} on a grid this large"));
+        }
+        if mode.needs_activity() != activity.tracked {
+            activity.reset(device, self.grid_size, mode.needs_activity());
+        }
+        self.color_mode = mode;
+        queue.write_buffer(&self.coloring_buf, 0, bytemuck::cast_slice(&self.palette.uniform_data(mode)));
+        Ok(())
+    }
+
+    /// The colors cells and the background are drawn with.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Draws with the colors of `palette` from the next frame on.
+    pub fn set_palette(&mut self, queue: &wgpu::Queue, palette: Palette) {
+        queue.write_buffer(&self.coloring_buf, 0, bytemuck::cast_slice(&palette.uniform_data(self.color_mode)));
+        self.palette = palette;
+    }
+
+    /// Replaces the current generation with `states`, one state per cell
+    /// (also for the packed layout), with row 0 at the bottom.
+    pub fn write_cells(&mut self, queue: &wgpu::Queue, states: &[u32]) {
+        assert_eq!(states.len(), self.grid_size.0 as usize * self.grid_size.1 as usize);
+        self.edited();
+        let current = &self.storage_buffs[(self.generation % 2) as usize];
+        if self.packed {
+            queue.write_buffer(current, 0, bytemuck::cast_slice(&cells::pack(states)));
+        } else {
+            queue.write_buffer(current, 0, bytemuck::cast_slice(states));
+        }
+    }
+
+    /// Replaces the current generation with a new one from the generator of
+    /// `options`, e.g. after changing its seed. `options` must describe the
+    /// same grid as the one the world was created with. The generation number
+    /// goes on.
+    pub fn reseed(&mut self, queue: &wgpu::Queue, options: &Options) {
+        self.edited();
+        let current = &self.storage_buffs[(self.generation % 2) as usize];
+        queue.write_buffer(current, 0, bytemuck::cast_slice(&seed::initial_cells(options)));
+    }
+
+    /// Reallocates the grid as `width` by `height` cells, keeping the current
+    /// generation centered in it, or cropped if the grid shrinks. The history
+    /// kept for rewinding is dropped, as it no longer fits.
+    ///
+    /// A grid growing past [`MAX_QUAD_CELLS`] is drawn by the fullscreen
+    /// renderer from then on, as a quad for every cell would take far more
+    /// work than the pixels they cover.
+    ///
+    /// This waits for the GPU to finish all submitted work.
+    pub fn resize_grid(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+    ) -> Result<(), String> {
+        if width == 0 || height == 0 {
+            return Err("the grid needs at least one cell".to_string());
+        }
+        if width > MAX_GRID_SIZE || height > MAX_GRID_SIZE {
+            return Err(format!("the grid cannot be larger than {MAX_GRID_SIZE}x{MAX_GRID_SIZE}"));
+        }
+        if self.packed && !width.is_multiple_of(32) {
+            return Err(format!("cannot resize to {width}x{height}, packed grids need a width that is a multiple of 32"));
+        }
+        let buffer_size = cell_buffer_size(device, (width, height), self.packed)?;
+
+        let states = cells::recenter(&self.read_cells(device, queue).states(), self.grid_size, (width, height));
+        let words = if self.packed { cells::pack(&states) } else { states };
+
+        self.storage_buffs = create_cell_buffers(device, buffer_size);
+        for buffer in &self.storage_buffs {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(&words));
+        }
+        self.history = create_history(device, self.history_wanted, buffer_size);
+        self.kept = None;
+        self.grid_size = (width, height);
+        queue.write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(&grid_uniform_data(self.grid_size, self.boundary, self.packed)));
+        self.bind_groups = create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &[&self.uniform_buf, &self.rule_buf, &self.table_buf, &self.census_buf, &self.camera_buf, &self.coloring_buf],
+            &self.storage_buffs,
+        );
+        if self.renderer == Renderer::Quads && u64::from(width) * u64::from(height) > MAX_QUAD_CELLS {
+            self.renderer = Renderer::Fullscreen;
+            if let (Some(surface_format), Some(activity)) = (self.surface_format, &self.activity) {
+                log::info!("Drawing the {width}x{height} grid with the fullscreen renderer");
+                self.render_pipeline = Some(create_render_pipeline(
+                    device,
+                    &self.cell_shader_module,
+                    [&self.bind_group_layout, &activity.render_layout],
+                    surface_format,
+                    self.renderer,
+                    false,
+                ));
+            }
+        }
+        if let Some(activity) = &mut self.activity {
+            activity.reset(device, self.grid_size, self.color_mode.needs_activity());
+            if self.color_mode.needs_activity() && !activity.tracked {
+                log::warn!("Cannot color cells by {} on a grid this large, coloring them by position", self.color_mode);
+                self.set_color_mode(device, queue, ColorMode::Position).unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    /// How the grid is drawn.
+    pub fn renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    /// The width and height of the grid in cells.
+    pub fn grid_size(&self) -> (u32, u32) {
+        self.grid_size
+    }
+
+    /// Number of generations simulated so far.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether the cells use the packed layout of 32 cells per word.
+    pub fn is_packed(&self) -> bool {
+        self.packed
+    }
+
+    /// The bind group whose input is the buffer holding the current generation.
+    fn current_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_groups[(self.generation % 2) as usize]
+    }
+
+    /// The cell containing `point`, given in cells as by
+    /// [`Camera::to_cells`], if it is inside the grid.
+    pub fn cell_at(&self, point: (f64, f64)) -> Option<(u32, u32)> {
+        let (width, height) = self.grid_size;
+        if !(0.0..f64::from(width)).contains(&point.0) || !(0.0..f64::from(height)).contains(&point.1) {
+            return None;
+        }
+        Some((point.0 as u32, point.1 as u32))
+    }
+
+    /// Shows the grid through `camera` in a window of `window_size`.
+    pub fn set_camera(&self, queue: &wgpu::Queue, camera: &Camera, window_size: PhysicalSize<u32>) {
+        queue.write_buffer(&self.camera_buf, 0, bytemuck::cast_slice(&camera.uniform_data(window_size)));
+    }
+
+    /// Sets every cell on the line from `from` to `to` to `cell_state` in the
+    /// current generation, so that fast mouse drags leave no gaps.
+    ///
+    /// A packed grid holds 32 cells in each word, so the rows the line crosses
+    /// are read back and written whole, which waits for the GPU.
+    pub fn paint_line(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        from: (u32, u32),
+        to: (u32, u32),
+        cell_state: u32,
+    ) {
+        self.edited();
+
+        // Bresenham's line algorithm.
+        let mut line = Vec::new();
+        let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+        let (dx, dy) = ((to.0 as i64 - x).abs(), -(to.1 as i64 - y).abs());
+        let (sx, sy) = (if x < to.0 as i64 { 1 } else { -1 }, if y < to.1 as i64 { 1 } else { -1 });
+        let mut error = dx + dy;
+        loop {
+            line.push((x as u32, y as u32));
+            if (x, y) == (to.0 as i64, to.1 as i64) {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+
+        let current = &self.storage_buffs[(self.generation % 2) as usize];
+        let width = self.grid_size.0;
+        if self.packed {
+            let row_size = u64::from(width / 32) * 4;
+            let bottom = from.1.min(to.1);
+            let rows = u64::from(bottom)..u64::from(from.1.max(to.1)) + 1;
+            let mut words = download_range(device, queue, current, rows.start * row_size..rows.end * row_size);
+            for (x, y) in line {
+                cells::set(&mut words, width, true, (x, y - bottom), cell_state);
+            }
+            queue.write_buffer(current, rows.start * row_size, bytemuck::cast_slice(&words));
+        } else {
+            for (x, y) in line {
+                let index = u64::from(y) * u64::from(width) + u64::from(x);
+                queue.write_buffer(current, index * 4, bytemuck::bytes_of(&cell_state));
+            }
+        }
+    }
+
+    /// Records the compute pass that advances the simulation by one generation.
+    pub fn step(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.step_within(encoder, 1);
+    }
+
+    /// Like [`World::step`], for a run of `remaining` steps in a row, this one
+    /// included. A generation the history would push out again before the run
+    /// ends is not kept, which spares a copy of the grid for all but the last
+    /// few generations of a long run.
+    pub fn step_within(&mut self, encoder: &mut wgpu::CommandEncoder, remaining: u64) {
+        // The history ends with the generation the run ends at, so only the
+        // generations less than its capacity before that are still in it.
+        if remaining < self.history.len() as u64 {
+            self.keep_current(encoder);
+        } else {
+            // Everything kept so far is pushed out by the end of the run, and
+            // the generations kept after this one no longer follow from it.
+            self.kept = None;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+        
+        compute_pass.set_pipeline(self.compute_pipeline.as_ref().unwrap());
+        compute_pass.set_bind_group(0, self.current_bind_group(), &[]);
+
+        // The packed kernel handles a word of 32 cells per invocation.
+        let (width, height) = self.grid_size;
+        let words_per_row = if self.packed { width / 32 } else { width };
+        compute_pass.dispatch_workgroups(words_per_row.div_ceil(8), height.div_ceil(8), 1);
+        drop(compute_pass);
+
+        self.generation += 1;
+        if let Some(activity) = self.activity.as_mut().filter(|activity| activity.tracked) {
+            let current = &self.bind_groups[(self.generation % 2) as usize];
+            dispatch_cells(encoder, &activity.track_pipeline, &[current, &activity.track_bind_group], self.grid_size);
+            activity.stale = false;
+        }
+    }
+
+    /// The generations that can be rewound to, from the oldest one kept to
+    /// the newest one simulated. Keeping the current generation in the
+    /// history may push out the oldest one, which is left out.
+    pub fn history(&self) -> RangeInclusive<u64> {
+        let capacity = self.history.len() as u64;
+        match self.kept {
+            // Rewound, so the current generation is kept already.
+            Some((oldest, newest)) if newest >= self.generation => oldest..=newest,
+            Some((oldest, newest)) if newest + 1 == self.generation && capacity > 0 => {
+                oldest.max((self.generation + 1).saturating_sub(capacity))..=self.generation
+            }
+            _ => self.generation..=self.generation,
+        }
+    }
+
+    /// Makes `generation` the current generation again, copying it back from
+    /// the history. The generations after it stay in the history, and can be
+    /// returned to, until the current generation is stepped or edited. Only
+    /// the cells are rewound, their ages and heat carry on from where they were.
+    ///
+    /// Panics if `generation` is not in [`World::history`].
+    pub fn rewind(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, generation: u64) {
+        assert!(self.history().contains(&generation), "generation {generation} is not kept");
+        if generation == self.generation {
+            return;
+        }
+        let mut encoder = device.create_command_encoder(&Default::default());
+        // The current generation needs to be kept to come back to it.
+        if !self.kept.is_some_and(|(oldest, newest)| (oldest..=newest).contains(&self.generation)) {
+            self.keep_current(&mut encoder);
+        }
+        let slot = &self.history[(generation % self.history.len() as u64) as usize];
+        let current = &self.storage_buffs[(generation % 2) as usize];
+        encoder.copy_buffer_to_buffer(slot, 0, current, 0, current.size());
+        queue.submit([encoder.finish()]);
+        self.generation = generation;
+        if let Some(activity) = &mut self.activity {
+            activity.stale = true;
+        }
+    }
+
+    /// Records copying the current generation into the history, where it
+    /// replaces the generations kept after it, and the oldest generation if
+    /// the history is full.
+    fn keep_current(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.history.is_empty() {
+            return;
+        }
+        let capacity = self.history.len() as u64;
+        let current = &self.storage_buffs[(self.generation % 2) as usize];
+        let slot = &self.history[(self.generation % capacity) as usize];
+        encoder.copy_buffer_to_buffer(current, 0, slot, 0, current.size());
+
+        let oldest = match self.kept {
+            Some((oldest, newest)) if (oldest..=newest + 1).contains(&self.generation) => {
+                oldest.max((self.generation + 1).saturating_sub(capacity))
+            }
+            _ => self.generation,
+        };
+        self.kept = Some((oldest, self.generation));
+    }
+
+    /// Forgets the kept copy of the current generation, which has changed, and
+    /// the generations kept after it, which no longer follow from it.
+    fn edited(&mut self) {
+        self.kept = match self.kept {
+            Some((_, newest)) if newest < self.generation => self.kept,
+            Some((oldest, _)) if oldest < self.generation => Some((oldest, self.generation - 1)),
+            _ => None,
+        };
+        if let Some(activity) = &mut self.activity {
+            activity.stale = true;
+        }
+    }
+
+    /// Records counting the neighbors of every cell again if the current
+    /// generation changed since they were last counted.
+    fn recount_if_stale(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(activity) = self.activity.as_mut().filter(|activity| activity.stale && activity.tracked) else {
+            return;
+        };
+        let current = &self.bind_groups[(self.generation % 2) as usize];
+        dispatch_cells(encoder, &activity.recount_pipeline, &[current, &activity.track_bind_group], self.grid_size);
+        activity.stale = false;
+    }
+
+    /// Records taking a census of the current generation, and copying it to
+    /// the start of `destination` as `CENSUS_SIZE` bytes: the population as a
+    /// `u32`, then the low and high `u32` of a 64-bit hash of the cells.
+    pub fn take_census(&self, encoder: &mut wgpu::CommandEncoder, destination: &wgpu::Buffer) {
+        encoder.clear_buffer(&self.census_buf, 0, None);
+
+        let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+        compute_pass.set_pipeline(&self.census_pipeline);
+        compute_pass.set_bind_group(0, self.current_bind_group(), &[]);
+        // 256 matches REDUCTION_SIZE in the shader. A few workgroups are
+        // enough to stream the grid, and each one ends with a single atomic
+        // addition, so larger grids are covered by counting more words per
+        // invocation instead.
+        let words = self.storage_buffs[0].size() / 4;
+        compute_pass.dispatch_workgroups(words.div_ceil(256).min(64) as u32, 1, 1);
+        drop(compute_pass);
+
+        encoder.copy_buffer_to_buffer(&self.census_buf, 0, destination, 0, World::CENSUS_SIZE);
+    }
+
+    /// Draws the current generation into `texture_view`.
+    pub fn render(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture_view: &wgpu::TextureView) {
+        // Renders a GREEN screen
+        let mut encoder = device.create_command_encoder(&Default::default());
+        self.recount_if_stale(&mut encoder);
+
+        // Create the renderpass which will clear the screen.
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.palette.clear_color()),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        // If you wanted to call any drawing commands, they would go here.
+        render_pass.set_pipeline(self.render_pipeline.as_ref().expect("World was created without a surface"));
+        render_pass.set_bind_group(0, self.current_bind_group(), &[]);
+        render_pass.set_bind_group(1, &self.activity.as_ref().unwrap().render_bind_group, &[]);
+        match self.renderer {
+            Renderer::Quads => {
+                render_pass.set_vertex_buffer(0, self.vertex_buf.as_ref().unwrap().slice(..));
+                render_pass.draw(
+                    0..self.num_vertices as u32, 
+                    0..(self.grid_size.0 * self.grid_size.1)
+                );
+            }
+            Renderer::Fullscreen => render_pass.draw(0..3, 0..1),
+        }
+
+        // End the renderpass.
+        drop(render_pass);
+
+        // Submit the command in the queue to execute
+        queue.submit([encoder.finish()]);
+    }
+}
@@ -0,0 +1,810 @@
+//! Runs the compute kernels headlessly and checks them against the CPU
+//! reference in `reference.rs`, generation by generation.
+//!
+//! A software adapter such as lavapipe or llvmpipe is preferred, so that the
+//! results do not depend on the machine's GPU. Without any adapter at all the
+//! tests fail, unless `SKIP_GPU_TESTS` is set to skip them on purpose.
+use first_wgpu_app::{
+    boundary::Boundary,
+    camera::Camera,
+    cells,
+    cli::{Kernel, Options, Renderer, MAX_GRID_SIZE},
+    coloring::{CellActivity, ColorMode, HEAT_DECAY, MAX_AGE, MAX_HEAT},
+    palette::Palette,
+    pattern::Pattern,
+    population::PopulationCounter,
+    reference::CpuWorld,
+    rule::Automaton,
+    seed::Generator,
+    stability::{Outcome, Stabilization, StabilityDetector},
+    world::World,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use winit::dpi::PhysicalSize;
+
+/// A grid whose sides are not multiples of the workgroup size, to cover
+/// partial workgroups, and differ, to catch swapped axes.
+const ODD_SIZE: (u32, u32) = (37, 23);
+/// The packed layout needs a width that is a multiple of 32.
+const PACKED_SIZE: (u32, u32) = (64, 37);
+
+fn gpu() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let request = |force_fallback_adapter| {
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            force_fallback_adapter,
+            ..Default::default()
+        }))
+    };
+    let Some(adapter) = request(true).or_else(|| request(false)) else {
+        if std::env::var_os("SKIP_GPU_TESTS").is_some() {
+            eprintln!("No adapter found, skipping GPU tests");
+            return None;
+        }
+        panic!("No adapter found; install lavapipe or llvmpipe, or set SKIP_GPU_TESTS to skip the GPU tests");
+    };
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            required_limits: adapter.limits(),
+            ..Default::default()
+        },
+        None,
+    ))
+    .expect("Failed to create device");
+    Some((device, queue))
+}
+
+fn automaton(spec: &str) -> Automaton {
+    Automaton::from_spec(spec).unwrap()
+}
+
+/// The configurations worth checking a rule in: both kernels on the unpacked
+/// layout, and the packed layout for Life-like rules.
+fn configurations(automaton: &Automaton) -> Vec<(Kernel, bool, (u32, u32))> {
+    let mut configurations = match automaton {
+        Automaton::Totalistic(_) => vec![(Kernel::Naive, false, ODD_SIZE), (Kernel::Tiled, false, ODD_SIZE)],
+        Automaton::Table(_) => vec![(Kernel::Naive, false, ODD_SIZE)],
+    };
+    if matches!(automaton, Automaton::Totalistic(rule) if rule.states == 2) {
+        configurations.push((Kernel::Naive, true, PACKED_SIZE));
+    }
+    configurations
+}
+
+/// The number of cells in which two grids differ.
+fn differences(a: &[u32], b: &[u32]) -> usize {
+    assert_eq!(a.len(), b.len());
+    a.iter().zip(b).filter(|(a, b)| a != b).count()
+}
+
+/// Steps `world` and `reference` side by side, comparing them every `every` generations.
+fn assert_same_evolution(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    world: &mut World,
+    reference: &mut CpuWorld,
+    generations: u32,
+    every: u32,
+    context: &str,
+) {
+    for generation in 1..=generations {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        world.step(&mut encoder);
+        queue.submit([encoder.finish()]);
+        reference.step();
+
+        if generation % every == 0 || generation == generations {
+            let states = world.read_cells(device, queue).states();
+            let differences = differences(&states, &reference.cells);
+            assert_eq!(differences, 0, "{context}: {differences} cells differ at generation {generation}");
+        }
+    }
+}
+
+fn check_random_soups(rules: &[&str], seed: u64) {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for &spec in rules {
+        let automaton = automaton(spec);
+        for (kernel, packed, size @ (width, height)) in configurations(&automaton) {
+            for boundary in Boundary::ALL {
+                let options = Options {
+                    automaton: automaton.clone(),
+                    boundary,
+                    grid_size: size,
+                    packed,
+                    kernel,
+                    ..Default::default()
+                };
+                let mut world = World::new(None, &device, &queue, &options);
+                let states = automaton.states();
+                let soup: Vec<u32> = (0..width * height)
+                    .map(|_| if rng.random_bool(0.4) { rng.random_range(1..states) } else { 0 })
+                    .collect();
+                world.write_cells(&queue, &soup);
+                let mut reference = CpuWorld::new(automaton.clone(), boundary, width, height, soup);
+
+                let context = format!("{spec} with {kernel}{} on a {boundary} grid", if packed { ", packed" } else { "" });
+                assert_same_evolution(&device, &queue, &mut world, &mut reference, 60, 20, &context);
+            }
+        }
+    }
+}
+
+#[test]
+fn life_like_rules_match_the_reference() {
+    check_random_soups(&["B3/S23", "B36/S23", "B1357/S1357"], 1);
+}
+
+#[test]
+fn generations_rules_match_the_reference() {
+    check_random_soups(&["B2/S/C3", "B34/S34/C6"], 2);
+}
+
+#[test]
+fn rule_tables_match_the_reference() {
+    check_random_soups(&["wireworld"], 3);
+}
+
+/// The cells of a grid holding `pattern` with its top left corner at `at`.
+fn stamped(size: u32, pattern: &Pattern, at: (u32, u32)) -> Vec<u32> {
+    let mut states = vec![0; (size * size) as usize];
+    assert_eq!(cells::stamp(&mut states, size, size, false, pattern, at), 0);
+    states
+}
+
+/// Runs `pattern` from the middle of a dead grid for `generations`, and checks
+/// that it ends up as itself moved by `shift` (right and down), on the GPU as
+/// well as in the reference.
+fn check_known_pattern(name: &str, generations: u32, shift: (u32, u32)) {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/patterns/");
+    let pattern = Pattern::load(format!("{path}{name}")).unwrap();
+    let automaton = automaton(pattern.rule.as_deref().unwrap_or("B3/S23"));
+
+    for (kernel, packed, _) in configurations(&automaton) {
+        // Large enough that nothing reaches the edges.
+        let size = 32;
+        let options = Options {
+            automaton: automaton.clone(),
+            generator: Generator::Pattern,
+            pattern: Some(pattern.clone()),
+            boundary: Boundary::Dead,
+            grid_size: (size, size),
+            packed,
+            kernel,
+            ..Default::default()
+        };
+        let mut world = World::new(None, &device, &queue, &options);
+        let at = cells::centered(size, size, &pattern);
+        let start = stamped(size, &pattern, at);
+        let placed = differences(&world.read_cells(&device, &queue).states(), &start);
+        assert_eq!(placed, 0, "{name} is not placed in the middle");
+        let mut reference = CpuWorld::new(automaton.clone(), Boundary::Dead, size, size, start);
+
+        let context = format!("{name} with {kernel}{}", if packed { ", packed" } else { "" });
+        assert_same_evolution(&device, &queue, &mut world, &mut reference, generations, 1, &context);
+        let expected = stamped(size, &pattern, (at.0 + shift.0, at.1 + shift.1));
+        let moved = differences(&reference.cells, &expected);
+        assert_eq!(moved, 0, "{context}: {moved} cells differ from the expected result");
+    }
+}
+
+#[test]
+fn oscillators_return_to_their_start() {
+    check_known_pattern("pulsar.lif", 3, (0, 0));
+    // The electron cuts the corners of the loop diagonally, so it takes 10 generations to go around.
+    check_known_pattern("wireworld_clock.rle", 10, (0, 0));
+}
+
+#[test]
+fn spaceships_move() {
+    // The glider moves one cell diagonally every four generations.
+    check_known_pattern("glider.rle", 4, (1, 1));
+    check_known_pattern("glider.rle", 40, (10, 10));
+}
+
+#[test]
+fn population_counts_match_the_reference() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let mut rng = StdRng::seed_from_u64(4);
+
+    for spec in ["B3/S23", "B34/S34/C6"] {
+        let automaton = automaton(spec);
+        for (kernel, packed, size @ (width, height)) in configurations(&automaton) {
+            let options = Options {
+                automaton: automaton.clone(),
+                grid_size: size,
+                packed,
+                kernel,
+                ..Default::default()
+            };
+            let mut world = World::new(None, &device, &queue, &options);
+            let states = automaton.states();
+            let soup: Vec<u32> = (0..width * height)
+                .map(|_| if rng.random_bool(0.4) { rng.random_range(1..states) } else { 0 })
+                .collect();
+            world.write_cells(&queue, &soup);
+            let mut reference = CpuWorld::new(automaton.clone(), Boundary::default(), width, height, soup);
+
+            let mut counter = PopulationCounter::new();
+            let mut expected = Vec::new();
+            for generation in 0..20 {
+                if generation > 0 {
+                    reference.step();
+                }
+                expected.push((generation, reference.cells.iter().filter(|&&state| state != 0).count() as u32));
+                let mut encoder = device.create_command_encoder(&Default::default());
+                if generation > 0 {
+                    world.step(&mut encoder);
+                }
+                let pending = counter.record(&device, &mut encoder, &world);
+                queue.submit([encoder.finish()]);
+                counter.submitted(pending);
+            }
+            device.poll(wgpu::Maintain::Wait);
+            let counted: Vec<_> = counter
+                .collect(&device)
+                .iter()
+                .map(|census| (census.generation, census.population))
+                .collect();
+
+            let context = format!("{spec} with {kernel}{}", if packed { ", packed" } else { "" });
+            assert_eq!(counted, expected, "{context}");
+        }
+    }
+}
+
+/// Runs `pattern` from the middle of a grid, under its own rule unless given
+/// another, until the GPU hashes show it settled, and returns how.
+fn settle(
+    name: &str,
+    rule: Option<&str>,
+    boundary: Boundary,
+    size: u32,
+    packed: bool,
+    generations: u64,
+) -> Option<Stabilization> {
+    let (device, queue) = gpu()?;
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/patterns/");
+    let pattern = Pattern::load(format!("{path}{name}")).unwrap();
+    let options = Options {
+        automaton: automaton(rule.or(pattern.rule.as_deref()).unwrap_or("B3/S23")),
+        generator: Generator::Pattern,
+        pattern: Some(pattern),
+        boundary,
+        grid_size: (size, size),
+        packed,
+        ..Default::default()
+    };
+    let mut world = World::new(None, &device, &queue, &options);
+    let mut counter = PopulationCounter::new();
+    let mut encoder = device.create_command_encoder(&Default::default());
+    let mut pending = vec![counter.record(&device, &mut encoder, &world)];
+    for _ in 0..generations {
+        world.step(&mut encoder);
+        pending.push(counter.record(&device, &mut encoder, &world));
+    }
+    queue.submit([encoder.finish()]);
+    pending.into_iter().for_each(|count| counter.submitted(count));
+    device.poll(wgpu::Maintain::Wait);
+
+    let mut detector = StabilityDetector::new(0);
+    let settled = counter.collect(&device).iter().find_map(|census| detector.observe(census));
+    assert!(settled.is_some(), "{name} did not settle within {generations} generations");
+    settled
+}
+
+#[test]
+fn hashes_find_oscillators_and_extinction() {
+    for packed in [false, true] {
+        let Some(pulsar) = settle("pulsar.lif", None, Boundary::Dead, 32, packed, 10) else {
+            return;
+        };
+        assert_eq!(pulsar, Stabilization { generation: 0, outcome: Outcome::Periodic { period: 3 } });
+
+        // On a torus the glider comes back to where it started after
+        // crossing the grid, a quarter of a cell per generation.
+        let glider = settle("glider.rle", None, Boundary::Torus, 32, packed, 200);
+        assert_eq!(glider, Some(Stabilization { generation: 0, outcome: Outcome::Periodic { period: 128 } }));
+    }
+
+    // Against a dead edge the glider turns into a still life.
+    let wall = settle("glider.rle", None, Boundary::Dead, 32, false, 200).unwrap();
+    assert_eq!(wall.outcome, Outcome::Periodic { period: 1 });
+    // Under B/S nothing is born and nothing survives.
+    let seeds = settle("glider.rle", Some("B/S"), Boundary::Dead, 32, false, 20).unwrap();
+    assert_eq!(seeds, Stabilization { generation: 1, outcome: Outcome::Extinct });
+}
+
+#[test]
+fn rewinding_restores_earlier_generations() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let (width, height) = ODD_SIZE;
+    let options = Options {
+        grid_size: ODD_SIZE,
+        history: 8,
+        ..Default::default()
+    };
+    let mut world = World::new(None, &device, &queue, &options);
+    let mut rng = StdRng::seed_from_u64(5);
+    let soup: Vec<u32> = (0..width * height).map(|_| u32::from(rng.random_bool(0.4))).collect();
+    world.write_cells(&queue, &soup);
+    let mut reference = CpuWorld::new(options.automaton.clone(), options.boundary, width, height, soup);
+    let mut generations = vec![reference.cells.clone()];
+    for _ in 0..10 {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        world.step(&mut encoder);
+        queue.submit([encoder.finish()]);
+        reference.step();
+        generations.push(reference.cells.clone());
+    }
+
+    // The current generation and the seven before it.
+    assert_eq!(world.history(), 3..=10);
+    for generation in [5, 9, 3, 10, 6] {
+        world.rewind(&device, &queue, generation);
+        assert_eq!(world.generation(), generation);
+        let differences = differences(&world.read_cells(&device, &queue).states(), &generations[generation as usize]);
+        assert_eq!(differences, 0, "{differences} cells differ after rewinding to generation {generation}");
+        assert_eq!(world.history(), 3..=10);
+    }
+
+    // Stepping resumes from the generation on screen and replaces the ones after it.
+    let mut encoder = device.create_command_encoder(&Default::default());
+    world.step(&mut encoder);
+    queue.submit([encoder.finish()]);
+    assert_eq!(world.history(), 3..=7);
+    assert_eq!(differences(&world.read_cells(&device, &queue).states(), &generations[7]), 0);
+
+    // So does editing.
+    world.rewind(&device, &queue, 4);
+    world.write_cells(&queue, &generations[4]);
+    assert_eq!(world.history(), 3..=4);
+}
+
+#[test]
+fn long_runs_keep_only_their_last_generations() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let (width, height) = ODD_SIZE;
+    let options = Options {
+        grid_size: ODD_SIZE,
+        history: 4,
+        ..Default::default()
+    };
+    let mut world = World::new(None, &device, &queue, &options);
+    let mut rng = StdRng::seed_from_u64(8);
+    let soup: Vec<u32> = (0..width * height).map(|_| u32::from(rng.random_bool(0.4))).collect();
+    world.write_cells(&queue, &soup);
+    let mut reference = CpuWorld::new(options.automaton.clone(), options.boundary, width, height, soup);
+    let mut generations = vec![reference.cells.clone()];
+    for _ in 0..20 {
+        reference.step();
+        generations.push(reference.cells.clone());
+    }
+
+    // Rewound first, so that the run also replaces the generations after the current one.
+    let mut encoder = device.create_command_encoder(&Default::default());
+    (0..3).for_each(|_| world.step(&mut encoder));
+    queue.submit([encoder.finish()]);
+    world.rewind(&device, &queue, 1);
+    let mut encoder = device.create_command_encoder(&Default::default());
+    for remaining in (1..=19).rev() {
+        world.step_within(&mut encoder, remaining);
+    }
+    queue.submit([encoder.finish()]);
+    assert_eq!(world.history(), 17..=20);
+    for generation in [17, 19, 18] {
+        world.rewind(&device, &queue, generation);
+        let differences = differences(&world.read_cells(&device, &queue).states(), &generations[generation as usize]);
+        assert_eq!(differences, 0, "{differences} cells differ after rewinding to generation {generation}");
+    }
+}
+
+#[test]
+fn large_grids_keep_fewer_generations() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    // 32 MiB a generation, so only 8 fit in the history budget.
+    let options = Options {
+        grid_size: (4096, 2048),
+        history: 64,
+        ..Default::default()
+    };
+    let mut world = World::new(None, &device, &queue, &options);
+    for _ in 0..9 {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        world.step(&mut encoder);
+        queue.submit([encoder.finish()]);
+    }
+    assert_eq!(world.history(), 2..=9);
+}
+
+#[test]
+fn resizing_keeps_the_cells_in_the_middle() {
+    // Rows count from the bottom, and odd margins leave the extra row or
+    // column at the far end.
+    assert_eq!(cells::recenter(&[1, 2, 3, 4], (2, 2), (4, 3)), [0, 1, 2, 0, 0, 3, 4, 0, 0, 0, 0, 0]);
+    assert_eq!(cells::recenter(&[1, 2, 3, 4, 5, 6, 7, 8, 9], (3, 3), (1, 1)), [5]);
+
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let automaton = automaton("B3/S23");
+    for (kernel, packed, size @ (width, height)) in configurations(&automaton) {
+        let options = Options {
+            automaton: automaton.clone(),
+            grid_size: size,
+            packed,
+            kernel,
+            ..Default::default()
+        };
+        let mut world = World::new(None, &device, &queue, &options);
+        let mut rng = StdRng::seed_from_u64(6);
+        let soup: Vec<u32> = (0..width * height).map(|_| u32::from(rng.random_bool(0.4))).collect();
+        world.write_cells(&queue, &soup);
+
+        let context = format!("{kernel}{}", if packed { ", packed" } else { "" });
+        let mut expected = soup;
+        let mut from = size;
+        // Growing and shrinking each side on its own, too.
+        for to in [(2 * width, 2 * height), (width + 32, height), (width / 2 + 32, height + 5), (2 * width, height / 3)] {
+            world.resize_grid(&device, &queue, to.0, to.1).unwrap();
+            assert_eq!(world.grid_size(), to);
+            expected = cells::recenter(&expected, from, to);
+            let differences = differences(&world.read_cells(&device, &queue).states(), &expected);
+            assert_eq!(differences, 0, "{context}: {differences} cells differ after resizing to {}x{}", to.0, to.1);
+            from = to;
+        }
+
+        // The simulation goes on at the new size.
+        let mut reference = CpuWorld::new(automaton.clone(), options.boundary, from.0, from.1, expected);
+        assert_same_evolution(&device, &queue, &mut world, &mut reference, 10, 5, &context);
+    }
+}
+
+#[test]
+fn painting_sets_and_clears_cells() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let (width, height) = (64, 48);
+    for packed in [false, true] {
+        let options = Options { grid_size: (width, height), packed, ..Default::default() };
+        let mut world = World::new(None, &device, &queue, &options);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut expected: Vec<u32> = (0..width * height).map(|_| u32::from(rng.random_bool(0.4))).collect();
+        world.write_cells(&queue, &expected);
+
+        // A row spanning both words of a packed row, a column, and a
+        // diagonal drawn backwards.
+        let lines = [((0, 5), (63, 5), 1), ((40, 47), (40, 0), 0), ((30, 20), (10, 0), 1)];
+        for (from, to, state) in lines {
+            world.paint_line(&device, &queue, from, to, state);
+        }
+        for x in 0..width {
+            expected[(5 * width + x) as usize] = 1;
+        }
+        for y in 0..height {
+            expected[(y * width + 40) as usize] = 0;
+        }
+        for i in 0..=20 {
+            expected[((20 - i) * width + 30 - i) as usize] = 1;
+        }
+
+        let differences = differences(&world.read_cells(&device, &queue).states(), &expected);
+        assert_eq!(differences, 0, "{differences} cells differ after painting{}", if packed { ", packed" } else { "" });
+    }
+}
+
+#[test]
+fn growing_past_the_quad_limit_switches_to_fullscreen() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let options = Options {
+        grid_size: (2048, 2048),
+        packed: true,
+        renderer: Renderer::Quads,
+        ..Default::default()
+    };
+    let mut world = World::new(Some(&wgpu::TextureFormat::Rgba8Unorm), &device, &queue, &options);
+    world.write_cells(&queue, &vec![1; 2048 * 2048]);
+
+    assert!(world.resize_grid(&device, &queue, MAX_GRID_SIZE + 1, 2048).is_err());
+    assert_eq!((world.grid_size(), world.renderer()), ((2048, 2048), Renderer::Quads));
+    world.resize_grid(&device, &queue, 4096, 2048).unwrap();
+    assert_eq!(world.renderer(), Renderer::Fullscreen);
+
+    // The live cells stay in the middle, with dead ones on either side.
+    let window = PhysicalSize::new(64, 32);
+    let pixels = draw(&device, &queue, &mut world, window);
+    let pixel = |x: u32, y: u32| pixels[(y * window.width + x) as usize];
+    assert_ne!(pixel(32, 16), pixel(2, 16));
+    assert_eq!(pixel(2, 16), pixel(61, 16));
+}
+
+/// Draws the current generation of `world`, created for `Rgba8Unorm`, into a
+/// `window` sized texture showing the whole grid, and reads back its pixels.
+fn draw(device: &wgpu::Device, queue: &wgpu::Queue, world: &mut World, window: PhysicalSize<u32>) -> Vec<[u8; 4]> {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Frame"),
+        size: wgpu::Extent3d { width: window.width, height: window.height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    world.set_camera(queue, &Camera::fit(world.grid_size(), window), window);
+    world.render(device, queue, &texture.create_view(&Default::default()));
+
+    // Rows of 256 bytes meet the alignment copies need.
+    assert_eq!(window.width % 64, 0);
+    let download = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Frame download"),
+        size: 4 * u64::from(window.width * window.height),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &download,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * window.width),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit([encoder.finish()]);
+    download.slice(..).map_async(wgpu::MapMode::Read, |mapped| mapped.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    let pixels = bytemuck::cast_slice(&download.slice(..).get_mapped_range()).to_vec();
+    pixels
+}
+
+/// Whether two pixels are equal up to rounding.
+fn close(a: [u8; 4], b: [u8; 4], tolerance: u8) -> bool {
+    a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= tolerance)
+}
+
+#[test]
+fn renderers_draw_the_same_picture() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let mut rng = StdRng::seed_from_u64(7);
+    let format = wgpu::TextureFormat::Rgba8Unorm;
+
+    for (spec, packed, size @ (width, height)) in [("B3/S23", false, ODD_SIZE), ("B3/S23", true, PACKED_SIZE), ("B2/S/C3", false, ODD_SIZE)] {
+        let automaton = automaton(spec);
+        let states = automaton.states();
+        let soup: Vec<u32> = (0..width * height)
+            .map(|_| if rng.random_bool(0.4) { rng.random_range(1..states) } else { 0 })
+            .collect();
+        // A few generations in, so that cells differ in age and heat.
+        let world = |renderer, colors| {
+            let options = Options {
+                automaton: automaton.clone(),
+                grid_size: size,
+                packed,
+                renderer,
+                colors,
+                ..Default::default()
+            };
+            let mut world = World::new(Some(&format), &device, &queue, &options);
+            world.write_cells(&queue, &soup);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            for _ in 0..3 {
+                world.step(&mut encoder);
+            }
+            queue.submit([encoder.finish()]);
+            world
+        };
+
+        // Four pixels per cell, so that cell edges fall between pixels, with
+        // room left over on the right.
+        let window = PhysicalSize::new((4 * width).next_multiple_of(64) + 64, 4 * height);
+        let mut pictures = Vec::new();
+        for colors in ColorMode::ALL {
+            let context = format!("{spec}{} on a {width}x{height} grid by {colors}", if packed { ", packed" } else { "" });
+            let quads = draw(&device, &queue, &mut world(Renderer::Quads, colors), window);
+            let fullscreen = draw(&device, &queue, &mut world(Renderer::Fullscreen, colors), window);
+            let differences = quads.iter().zip(&fullscreen).filter(|&(&a, &b)| !close(a, b, 1)).count();
+            assert_eq!(differences, 0, "{context}: {differences} pixels differ between the renderers");
+            assert!(!pictures.contains(&quads), "{context}: looks like another color mode");
+            pictures.push(quads);
+        }
+    }
+
+    // Zoomed out to two cells per pixel each way, smoothing shows the average
+    // of the four cells of each pixel, as drawn with one pixel per cell.
+    let (width, height) = (128, 64);
+    let soup: Vec<u32> = (0..width * height).map(|_| u32::from(rng.random_bool(0.4))).collect();
+    let world = |smooth| {
+        let options = Options {
+            grid_size: (width, height),
+            renderer: Renderer::Fullscreen,
+            smooth,
+            ..Default::default()
+        };
+        let mut world = World::new(Some(&format), &device, &queue, &options);
+        world.write_cells(&queue, &soup);
+        world
+    };
+    let sharp = draw(&device, &queue, &mut world(false), PhysicalSize::new(width, height));
+    let smooth = draw(&device, &queue, &mut world(true), PhysicalSize::new(width / 2, height / 2));
+    for (i, &pixel) in smooth.iter().enumerate() {
+        let (x, y) = (2 * (i as u32 % (width / 2)), 2 * (i as u32 / (width / 2)));
+        let cells = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].map(|(x, y)| sharp[(y * width + x) as usize]);
+        let average = std::array::from_fn(|c| (cells.iter().map(|cell| u32::from(cell[c])).sum::<u32>() / 4) as u8);
+        assert!(close(pixel, average, 2), "pixel {i} is {pixel:?} instead of about {average:?}");
+    }
+}
+
+#[test]
+fn palettes_color_the_background_and_cells() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let format = wgpu::TextureFormat::Rgba8Unorm;
+    // The left half of the grid alive, drawn 4 pixels per cell in the middle
+    // of a window twice as wide.
+    let (width, height) = (16, 16);
+    let cells: Vec<u32> = (0..width * height).map(|i| u32::from(i % width < width / 2)).collect();
+    let window = PhysicalSize::new(8 * width, 4 * height);
+    // The middle row: the background, then a live and a dead cell.
+    let row = (2 * height * window.width) as usize;
+    let pixels = [row + 2, row + (2 * width + 2) as usize, row + (6 * width - 2) as usize];
+    let palette = Palette::parse(
+        "background = \"#00ff00\"\nalive = \"#0000ff\"\ndead = \"#ff000080\"",
+        "test",
+    )
+    .unwrap();
+
+    for renderer in [Renderer::Quads, Renderer::Fullscreen] {
+        let options = Options {
+            grid_size: (width, height),
+            renderer,
+            ..Default::default()
+        };
+        let mut world = World::new(Some(&format), &device, &queue, &options);
+        world.write_cells(&queue, &cells);
+        // The built-in palette: dark blue behind a gradient, dead cells not drawn.
+        let picture = draw(&device, &queue, &mut world, window);
+        let [background, alive, dead] = pixels.map(|i| picture[i]);
+        assert!(close(background, [0, 0, 77, 255], 1), "{renderer}: {background:?}");
+        assert!(alive != background && alive[3] == 255, "{renderer}: {alive:?}");
+        assert_eq!(dead, background, "{renderer}");
+
+        world.set_palette(&queue, palette.clone());
+        let picture = draw(&device, &queue, &mut world, window);
+        let [background, alive, dead] = pixels.map(|i| picture[i]);
+        assert_eq!(background, [0, 255, 0, 255], "{renderer}");
+        assert_eq!(alive, [0, 0, 255, 255], "{renderer}");
+        // Half transparent red over the background.
+        assert!(close(dead, [128, 127, 0, 255], 1), "{renderer}: {dead:?}");
+    }
+}
+
+/// The activity the window should show for `reference`, after `previous`
+/// changed into it and had the activity `last`.
+fn expected_activity(reference: &CpuWorld, previous: &[u32], last: &[CellActivity]) -> Vec<CellActivity> {
+    let width = reference.width as usize;
+    reference
+        .cells
+        .iter()
+        .enumerate()
+        .map(|(i, &state)| {
+            let (x, y) = ((i % width) as i64, (i / width) as i64);
+            let offsets = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+            CellActivity {
+                age: if state == 0 { 0 } else { (last[i].age + 1).min(MAX_AGE) },
+                heat: if state == previous[i] { (last[i].heat as f32 * HEAT_DECAY) as u32 } else { MAX_HEAT },
+                neighbors: offsets.iter().filter(|(dx, dy)| reference.state(x + dx, y + dy) == 1).count() as u32,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn activity_tracks_age_heat_and_neighbors() {
+    let Some((device, queue)) = gpu() else {
+        return;
+    };
+    let mut rng = StdRng::seed_from_u64(8);
+
+    for (spec, packed, size @ (width, height), boundary) in [
+        ("B3/S23", false, ODD_SIZE, Boundary::Klein),
+        ("B3/S23", true, PACKED_SIZE, Boundary::Dead),
+        ("B2/S/C3", false, ODD_SIZE, Boundary::Torus),
+    ] {
+        let automaton = automaton(spec);
+        let options = Options {
+            automaton: automaton.clone(),
+            boundary,
+            grid_size: size,
+            packed,
+            colors: ColorMode::Age,
+            ..Default::default()
+        };
+        let context = format!("{spec}{} on a {boundary} grid", if packed { ", packed" } else { "" });
+        let states = automaton.states();
+        let mut soup = || -> Vec<u32> {
+            (0..width * height)
+                .map(|_| if rng.random_bool(0.4) { rng.random_range(1..states) } else { 0 })
+                .collect()
+        };
+
+        // Only worlds that are drawn keep track.
+        assert_eq!(World::new(None, &device, &queue, &options).read_activity(&device, &queue), None);
+
+        let mut world = World::new(Some(&wgpu::TextureFormat::Rgba8Unorm), &device, &queue, &options);
+        let start = soup();
+        world.write_cells(&queue, &start);
+        let mut reference = CpuWorld::new(automaton.clone(), boundary, width, height, start);
+        let mut expected = vec![CellActivity::default(); reference.cells.len()];
+        for generation in 1..=30 {
+            let previous = reference.cells.clone();
+            let mut encoder = device.create_command_encoder(&Default::default());
+            world.step(&mut encoder);
+            queue.submit([encoder.finish()]);
+            reference.step();
+            expected = expected_activity(&reference, &previous, &expected);
+            if generation % 10 == 0 {
+                let activity = world.read_activity(&device, &queue).unwrap();
+                let differences = activity.iter().zip(&expected).filter(|(a, b)| a != b).count();
+                assert_eq!(differences, 0, "{context}: {differences} cells differ at generation {generation}");
+            }
+        }
+
+        // Editing recounts the neighbors, and erased cells lose their age.
+        let edited = soup();
+        world.write_cells(&queue, &edited);
+        reference.cells = edited;
+        let recounted = expected_activity(&reference, &reference.cells, &expected);
+        let expected: Vec<CellActivity> = recounted
+            .iter()
+            .zip(&expected)
+            .zip(&reference.cells)
+            .map(|((recounted, last), &state)| CellActivity {
+                age: if state == 0 { 0 } else { last.age },
+                heat: last.heat,
+                neighbors: recounted.neighbors,
+            })
+            .collect();
+        let activity = world.read_activity(&device, &queue).unwrap();
+        let differences = activity.iter().zip(&expected).filter(|(a, b)| a != b).count();
+        assert_eq!(differences, 0, "{context}: {differences} cells differ after an edit");
+
+        // Coloring by position drops the activity, and coloring by it again
+        // starts every cell over, with its neighbors counted.
+        world.set_color_mode(&device, &queue, ColorMode::Position).unwrap();
+        assert_eq!(world.read_activity(&device, &queue), None, "{context}");
+        world.set_color_mode(&device, &queue, ColorMode::Heat).unwrap();
+        let expected: Vec<CellActivity> = recounted
+            .iter()
+            .map(|recounted| CellActivity { neighbors: recounted.neighbors, ..CellActivity::default() })
+            .collect();
+        let activity = world.read_activity(&device, &queue).unwrap();
+        let differences = activity.iter().zip(&expected).filter(|(a, b)| a != b).count();
+        assert_eq!(differences, 0, "{context}: {differences} cells differ after switching colors");
+    }
+}